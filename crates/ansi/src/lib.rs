@@ -12,10 +12,10 @@ use ::{
     crossterm::{
         cursor::{MoveTo, MoveToNextLine},
         event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
-        style::Print,
+        style::{Attribute, Color, Print, SetAttribute, SetBackgroundColor, SetForegroundColor},
         terminal::{
-            disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen,
-            LeaveAlternateScreen,
+            disable_raw_mode, enable_raw_mode, size, Clear, ClearType, EnterAlternateScreen,
+            LeaveAlternateScreen, SetTitle,
         },
         Command, QueueableCommand,
     },
@@ -23,20 +23,111 @@ use ::{
         fmt,
         io::{stdout, Write},
         sync::{Arc, Mutex},
+        time::Duration,
     },
-    tokio::runtime,
+    tokio::{runtime, sync::broadcast},
 };
 
-use context::{Directive, Directives, Handle};
+use context::{
+    Case, Chords, CmRDT, Code, Directive, Directives, Frame, Handle, Key, Keymap, Modifiers, Op,
+    Panel, Position, Press, Query, Reply, Severity, Signal, Source, Style,
+};
+
+/// How long to wait on the terminal before checking for changes on disk.
+static REFRESH: Duration = Duration::from_millis(250);
 
 pub struct Context {
     inner: Arc<Mutex<context::Context>>,
+    keymap: Keymap,
+    chords: Mutex<Chords>,
+    viewport: Mutex<Viewport>,
+    prompt: Mutex<Option<Query>>,
+    panels: Mutex<Panels>,
+    events: Mutex<broadcast::Receiver<context::Event>>,
+}
+
+/// The panels that are opened and closed by key: the log, with how many
+/// notes there were when it was last open so warnings and errors since can
+/// be shown while it is closed, and the list of bindings.
+#[derive(Default)]
+struct Panels {
+    log: bool,
+    seen: usize,
+    bindings: bool,
+}
+
+/// The lines on screen: `top` is the first shown and `rows` how many fit.
+#[derive(Default)]
+struct Viewport {
+    top: usize,
+    rows: usize,
+}
+
+/// Draws text the way a span of the view looks.
+struct Look<'a>(&'a context::Look);
+
+impl Command for Look<'_> {
+    fn write_ansi(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        SetAttribute(Attribute::Reset).write_ansi(out)?;
+
+        if self.0.selected {
+            SetAttribute(Attribute::Reverse).write_ansi(out)?;
+        }
+
+        if self.0.matched {
+            SetAttribute(Attribute::Underlined).write_ansi(out)?;
+        }
+
+        for style in &self.0.styles {
+            match style {
+                Style::Bold => SetAttribute(Attribute::Bold).write_ansi(out)?,
+                Style::Italic => SetAttribute(Attribute::Italic).write_ansi(out)?,
+                Style::Underline => SetAttribute(Attribute::Underlined).write_ansi(out)?,
+                Style::Highlight => SetBackgroundColor(Color::DarkYellow).write_ansi(out)?,
+                Style::Link(_) => {
+                    SetForegroundColor(Color::Blue).write_ansi(out)?;
+                    SetAttribute(Attribute::Underlined).write_ansi(out)?;
+                }
+                Style::Diagnostic(_) => {
+                    SetForegroundColor(Color::Red).write_ansi(out)?;
+                    SetAttribute(Attribute::Underlined).write_ansi(out)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl From<context::Context> for Context {
-    fn from(inner: context::Context) -> Self {
+    fn from(mut inner: context::Context) -> Self {
+        let rows = size().map_or(24, |(_, rows)| rows as usize);
+        let events = Mutex::new(inner.subscribe());
+
+        let keymap = match Keymap::load() {
+            Ok(keymap) => keymap,
+            Err(error) => {
+                let op = inner.log().record(
+                    Severity::Warning,
+                    Source::Session,
+                    format!("keymap not loaded, using the default: {}", error),
+                    inner.actor(),
+                );
+
+                inner.apply(Op::Log(op));
+
+                Keymap::default()
+            }
+        };
+
         Self {
             inner: Arc::new(Mutex::new(inner)),
+            keymap,
+            chords: Mutex::new(Chords::default()),
+            viewport: Mutex::new(Viewport { top: 0, rows }),
+            prompt: Mutex::new(None),
+            panels: Mutex::new(Panels::default()),
+            events,
         }
     }
 }
@@ -44,32 +135,447 @@ impl From<context::Context> for Context {
 impl Command for Context {
     fn write_ansi(&self, out: &mut impl fmt::Write) -> fmt::Result {
         let inner = self.inner.lock().map_err(|_| fmt::Error)?;
+        let mut viewport = self.viewport.lock().map_err(|_| fmt::Error)?;
+        let prompt = self.prompt.lock().map_err(|_| fmt::Error)?;
+        let mut panels = self.panels.lock().map_err(|_| fmt::Error)?;
+
+        let frame = Frame {
+            top: viewport.top,
+            rows: Some(viewport.rows),
+            prompt: prompt.clone(),
+            log: panels.log,
+            seen: panels.seen,
+            bindings: match panels.bindings {
+                true => self.keymap.bindings(),
+                false => vec![],
+            },
+        };
+
+        let view = inner.view(&frame).map_err(|_| fmt::Error)?;
+
+        viewport.top = view.top;
+
+        if panels.log {
+            panels.seen = inner.log().len();
+        }
+
+        let dirty = match view.status.dirty {
+            true => "*",
+            false => "",
+        };
 
-        for c in inner.buffer().iter() {
-            if c == &'\n' {
+        SetTitle(format!(
+            "{}{} {}:{}",
+            view.status.name,
+            dirty,
+            view.status.position.line + 1,
+            view.status.position.column + 1
+        ))
+        .write_ansi(out)?;
+
+        for (row, line) in view.lines.iter().enumerate() {
+            if row > 0 {
+                Clear(ClearType::UntilNewLine).write_ansi(out)?;
                 MoveToNextLine(1).write_ansi(out)?;
-            } else {
-                Print(*c).write_ansi(out)?;
             }
+
+            for span in &line.spans {
+                Look(&span.look).write_ansi(out)?;
+                Print(&span.text).write_ansi(out)?;
+            }
+
+            SetAttribute(Attribute::Reset).write_ansi(out)?;
         }
 
-        Ok(())
+        for panel in &view.panels {
+            match panel {
+                Panel::Bindings(bindings) => {
+                    for binding in bindings {
+                        Clear(ClearType::UntilNewLine).write_ansi(out)?;
+                        MoveToNextLine(1).write_ansi(out)?;
+                        Print(format!("{:<16} {}", binding.keys, binding.command))
+                            .write_ansi(out)?;
+                    }
+                }
+                Panel::Log(notes) => {
+                    for note in notes {
+                        let color = match note.severity {
+                            Severity::Info => Color::DarkGrey,
+                            Severity::Warning => Color::DarkYellow,
+                            Severity::Error => Color::Red,
+                        };
+
+                        Clear(ClearType::UntilNewLine).write_ansi(out)?;
+                        MoveToNextLine(1).write_ansi(out)?;
+                        SetForegroundColor(color).write_ansi(out)?;
+                        Print(note).write_ansi(out)?;
+                        SetAttribute(Attribute::Reset).write_ansi(out)?;
+                    }
+                }
+                Panel::Alerts(notes) => {
+                    for note in notes {
+                        Clear(ClearType::UntilNewLine).write_ansi(out)?;
+                        MoveToNextLine(1).write_ansi(out)?;
+                        Print(format!("! {}", note.message)).write_ansi(out)?;
+                    }
+                }
+                Panel::Diagnostics(messages) => {
+                    for message in messages {
+                        Clear(ClearType::UntilNewLine).write_ansi(out)?;
+                        MoveToNextLine(1).write_ansi(out)?;
+                        Print(format!("~ {}", message)).write_ansi(out)?;
+                    }
+                }
+                Panel::Conflict => {
+                    Clear(ClearType::UntilNewLine).write_ansi(out)?;
+                    MoveToNextLine(1).write_ansi(out)?;
+                    Print("! changed on disk: ctrl-r reloads it, ctrl-s saves over it")
+                        .write_ansi(out)?;
+                }
+                Panel::Search(query) => {
+                    Clear(ClearType::UntilNewLine).write_ansi(out)?;
+                    MoveToNextLine(1).write_ansi(out)?;
+                    Print(format!(
+                        "/{} [{}] [{:?} case] [{}] alt-r, alt-c, alt-w toggle",
+                        query.pattern,
+                        if query.regex { "regex" } else { "literal" },
+                        query.case,
+                        if query.whole_word { "word" } else { "any" },
+                    ))
+                    .write_ansi(out)?;
+                }
+                Panel::Documents(tabs) => {
+                    let tabs = tabs
+                        .iter()
+                        .map(|tab| {
+                            let dirty = match tab.dirty {
+                                true => "*",
+                                false => "",
+                            };
+
+                            match tab.current {
+                                true => format!("[{}{}]", tab.name, dirty),
+                                false => format!("{}{}", tab.name, dirty),
+                            }
+                        })
+                        .collect::<Vec<_>>();
+
+                    Clear(ClearType::UntilNewLine).write_ansi(out)?;
+                    MoveToNextLine(1).write_ansi(out)?;
+                    Print(tabs.join(" ")).write_ansi(out)?;
+                }
+            }
+        }
+
+        Clear(ClearType::FromCursorDown).write_ansi(out)?;
+
+        let before: String = view
+            .lines
+            .get(view.cursor.line)
+            .map(|line| line.text().chars().take(view.cursor.column).collect())
+            .unwrap_or_default();
+
+        MoveTo(context::width(&before) as u16, view.cursor.line as u16).write_ansi(out)
     }
 }
 
 impl Handle for Context {
     type Event = Event;
-    type Directive = Directives;
+    type Directive = Reply;
 
+    /// Handles `event`, then takes what it changed in the context.
     fn handle(&self, event: &Self::Event) -> Self::Directive {
+        let mut reply = match self.search(event) {
+            Some(reply) => reply,
+            None => self.key(event),
+        };
+
+        reply.merge(self.pending());
+
+        reply
+    }
+}
+
+impl Context {
+    /// Handles keys through the keymap, and the size of the terminal.
+    fn key(&self, event: &Event) -> Reply {
         match event {
-            Event::Key(KeyEvent {
-                code: KeyCode::Char('c'),
-                modifiers: KeyModifiers::CONTROL,
-            }) => Directives::STOP,
-            _ => Directives::empty(),
+            Event::Key(event) => {
+                let press = match (key(event), self.chords.lock()) {
+                    (Some(key), Ok(mut chords)) => self.keymap.press(&mut chords, key),
+                    _ => return Reply::default(),
+                };
+
+                self.press(press)
+            }
+            Event::Resize(columns, rows) => Reply::default().signal(Signal::Resize {
+                columns: *columns as usize,
+                rows: *rows as usize,
+            }),
+            _ => Reply::default(),
         }
     }
+
+    /// Runs the command a press came to, or types the key if it is a single
+    /// one bound to nothing.
+    fn press(&self, press: Press) -> Reply {
+        match press {
+            Press::Command(command) => self.command(&command),
+            Press::Pending => Reply::default(),
+            Press::Unbound(keys) => {
+                if let [Key {
+                    code: Code::Char(c),
+                    modifiers,
+                }] = keys.as_slice()
+                {
+                    if let (true, Ok(mut inner)) = (modifiers.is_empty(), self.inner.lock()) {
+                        inner.apply(Op::Type(c.to_string()));
+                    }
+                }
+
+                Reply::default()
+            }
+        }
+    }
+
+    /// Does what `command` stands for in the terminal, or applies its op.
+    fn command(&self, command: &context::Command) -> Reply {
+        match command {
+            context::Command::Quit => Directives::STOP.into(),
+            context::Command::PageUp | context::Command::PageDown => {
+                let pages = match command {
+                    context::Command::PageUp => -1,
+                    _ => 1,
+                };
+
+                match self.page(pages) {
+                    Some((op, lines)) => {
+                        if let Ok(mut inner) = self.inner.lock() {
+                            inner.apply(op);
+                        }
+
+                        Reply::default().signal(Signal::Scroll(lines))
+                    }
+                    None => Reply::default(),
+                }
+            }
+            context::Command::Search => {
+                if let Ok(mut prompt) = self.prompt.lock() {
+                    *prompt = Some(Query::default());
+                }
+
+                Directives::REDRAW.into()
+            }
+            context::Command::ToggleLog => {
+                if let Ok(mut panels) = self.panels.lock() {
+                    panels.log = !panels.log;
+                }
+
+                Directives::REDRAW.into()
+            }
+            context::Command::Bindings => {
+                if let Ok(mut panels) = self.panels.lock() {
+                    panels.bindings = !panels.bindings;
+                }
+
+                Directives::REDRAW.into()
+            }
+            _ => {
+                if let Ok(mut inner) = self.inner.lock() {
+                    command.apply(&mut inner);
+                }
+
+                Reply::default()
+            }
+        }
+    }
+
+    /// Handles keys while the search prompt is open, leaving everything else,
+    /// and any key once it is closed, to `handle`.
+    fn search(&self, event: &Event) -> Option<Reply> {
+        let mut prompt = self.prompt.lock().ok()?;
+        let query = prompt.as_mut()?;
+
+        let (code, modifiers) = match event {
+            Event::Key(KeyEvent { code, modifiers }) => (code, *modifiers),
+            _ => return None,
+        };
+
+        let op = match code {
+            KeyCode::Esc => {
+                *prompt = None;
+                Op::EndSearch
+            }
+            KeyCode::Enter | KeyCode::Down => Op::FindNext,
+            KeyCode::Up => Op::FindPrevious,
+            KeyCode::Backspace => {
+                query.pattern.pop();
+                Op::Search(query.clone())
+            }
+            KeyCode::Char('r') if modifiers == KeyModifiers::ALT => {
+                query.regex = !query.regex;
+                Op::Search(query.clone())
+            }
+            KeyCode::Char('c') if modifiers == KeyModifiers::ALT => {
+                query.case = match query.case {
+                    Case::Smart => Case::Sensitive,
+                    Case::Sensitive => Case::Insensitive,
+                    Case::Insensitive => Case::Smart,
+                };
+                Op::Search(query.clone())
+            }
+            KeyCode::Char('w') if modifiers == KeyModifiers::ALT => {
+                query.whole_word = !query.whole_word;
+                Op::Search(query.clone())
+            }
+            KeyCode::Char(c) if (modifiers - KeyModifiers::SHIFT).is_empty() => {
+                query.pattern.push(*c);
+                Op::Search(query.clone())
+            }
+            _ => return None,
+        };
+
+        if let Ok(mut inner) = self.inner.lock() {
+            // A regex is often invalid halfway through typing it, so only
+            // search once it compiles rather than logging every keystroke.
+            if inner.validate_op(&op).is_ok() {
+                inner.apply(op);
+            }
+        }
+
+        Some(Directives::REDRAW.into())
+    }
+
+    /// The op moving the cursor a screen up or down for each of `pages`, and
+    /// how many lines that is.
+    fn page(&self, pages: isize) -> Option<(Op, isize)> {
+        let inner = self.inner.lock().ok()?;
+        let rows = self.viewport.lock().ok()?.rows.max(1) as isize;
+
+        let buffer = inner.buffer();
+
+        let head = buffer.selections(inner.actor()).ok()?.last()?.head;
+        let position = buffer.offset_to_position(head).ok()?;
+        let last = buffer.line_count().ok()?.saturating_sub(1);
+
+        let line = (position.line as isize + pages * rows).clamp(0, last as isize) as usize;
+        let offset = buffer
+            .position_to_offset(Position::new(line, position.column))
+            .ok()?;
+
+        Some((
+            Op::Select(offset..offset),
+            line as isize - position.line as isize,
+        ))
+    }
+
+    /// Applies whatever changed on disk and ends a key sequence left
+    /// unfinished for too long, then takes what changed since the screen was
+    /// last drawn.
+    fn refresh(&self) -> Reply {
+        if let Ok(mut inner) = self.inner.lock() {
+            if inner.changed() {
+                inner.apply(Op::Refresh);
+            }
+        }
+
+        let expired = self
+            .chords
+            .lock()
+            .ok()
+            .and_then(|mut chords| self.keymap.expire(&mut chords));
+
+        let mut reply = match expired {
+            Some(press) => self.press(press),
+            None => Reply::default(),
+        };
+
+        reply.merge(self.pending());
+
+        reply
+    }
+
+    /// What to do about the events sent since the last call.
+    fn pending(&self) -> Reply {
+        let mut reply = Reply::default();
+
+        let mut events = match self.events.lock() {
+            Ok(events) => events,
+            Err(_) => return reply,
+        };
+
+        loop {
+            match events.try_recv() {
+                Ok(event) => reply.merge(Reply::from(&event)),
+                Err(broadcast::error::TryRecvError::Lagged(_)) => {
+                    reply.merge(Directives::REDRAW.into())
+                }
+                Err(_) => return reply,
+            }
+        }
+    }
+
+    /// Acts on the signals in `reply`: scrolls or resizes the viewport, goes
+    /// to the top of a document on focusing it, and logs saving one.
+    fn direct(&self, reply: &Reply) {
+        for signal in &reply.signals {
+            match signal {
+                Signal::Scroll(lines) => {
+                    if let Ok(mut viewport) = self.viewport.lock() {
+                        viewport.top = viewport.top.saturating_add_signed(*lines);
+                    }
+                }
+                Signal::Resize { rows, .. } => {
+                    if let Ok(mut viewport) = self.viewport.lock() {
+                        viewport.rows = *rows;
+                    }
+                }
+                Signal::Focus(_) => {
+                    if let Ok(mut viewport) = self.viewport.lock() {
+                        viewport.top = 0;
+                    }
+                }
+                // The context logs the save itself.
+                Signal::Save(_) => {}
+            }
+        }
+    }
+}
+
+/// The key `event` is for in a keymap, if it has one.
+fn key(event: &KeyEvent) -> Option<Key> {
+    let code = match event.code {
+        KeyCode::Char(c) => Code::Char(c),
+        KeyCode::Enter => Code::Enter,
+        KeyCode::Esc => Code::Esc,
+        KeyCode::Backspace => Code::Backspace,
+        KeyCode::Delete => Code::Delete,
+        KeyCode::Tab => Code::Tab,
+        KeyCode::Left => Code::Left,
+        KeyCode::Right => Code::Right,
+        KeyCode::Up => Code::Up,
+        KeyCode::Down => Code::Down,
+        KeyCode::Home => Code::Home,
+        KeyCode::End => Code::End,
+        KeyCode::PageUp => Code::PageUp,
+        KeyCode::PageDown => Code::PageDown,
+        KeyCode::F(number) => Code::F(number),
+        _ => return None,
+    };
+
+    let mut modifiers = Modifiers::empty();
+
+    for (from, to) in [
+        (KeyModifiers::CONTROL, Modifiers::CONTROL),
+        (KeyModifiers::ALT, Modifiers::ALT),
+        (KeyModifiers::SHIFT, Modifiers::SHIFT),
+    ] {
+        if event.modifiers.contains(from) {
+            modifiers |= to;
+        }
+    }
+
+    Some(Key::new(code, modifiers))
 }
 
 pub fn watch(context: impl Into<Context>) -> Result<(), Error> {
@@ -89,20 +595,34 @@ pub fn watch(context: impl Into<Context>) -> Result<(), Error> {
             .flush()?;
 
         loop {
-            let directives = match event::read() {
-                Ok(event) => context.handle(&event),
-                _ => break,
+            let reply = match event::poll(REFRESH)? {
+                true => match event::read() {
+                    Ok(event) => context.handle(&event),
+                    _ => break,
+                },
+                false => context.refresh(),
             };
 
-            buffer
-                .queue(Clear(ClearType::All))?
-                .queue(MoveTo(0, 0))?
-                .queue(&context)?
-                .flush()?;
-
-            if directives.stop() {
+            if reply.stop() {
                 break;
             }
+
+            context.direct(&reply);
+
+            if !reply.redraw() {
+                continue;
+            }
+
+            // Drawing writes over what was there, so the screen is only
+            // cleared when everything on it moves.
+            if reply
+                .directives
+                .intersects(Directives::RESIZE | Directives::FOCUS_CHANGE)
+            {
+                buffer.queue(Clear(ClearType::All))?;
+            }
+
+            buffer.queue(MoveTo(0, 0))?.queue(&context)?.flush()?;
         }
 
         buffer.queue(LeaveAlternateScreen)?.flush()?;
@@ -6,20 +6,350 @@ pub enum Error {
 
 use dioxus::prelude::*;
 
-use ::{context::Context, instrument::prelude::*};
+use ::{
+    context::{
+        Chords, Code, Command, Context, Directive, Directives, Frame, Key, Keymap, Look, Modifiers,
+        Op, Panel, Press, Query, Reply, Severity, Style,
+    },
+    instrument::prelude::*,
+    std::sync::Mutex,
+    tokio::sync::broadcast::error::RecvError,
+    web_sys::XmlHttpRequest,
+};
+
+/// The context, along with the keys pressed towards a binding and whether
+/// the log and the bindings are listed.
+pub struct Props {
+    context: Mutex<Context>,
+    keymap: Keymap,
+    chords: Mutex<Chords>,
+    log: Mutex<bool>,
+    bindings: Mutex<bool>,
+}
 
 pub fn watch(context: Context) -> Result<(), Error> {
     warn!("launching: {}", context);
 
-    dioxus::web::launch_with_props(app, context.into(), |c| c);
+    let props = Props {
+        context: Mutex::new(context),
+        keymap: keymap(),
+        chords: Mutex::new(Chords::default()),
+        log: Mutex::new(true),
+        bindings: Mutex::new(false),
+    };
+
+    dioxus::web::launch_with_props(app, props, |c| c);
 
     Ok(())
 }
 
-fn app(scope: Scope<Context>) -> Element {
-    let context = scope.props;
+/// The keymap the server reads from the config directory, or the default
+/// one if it can't be fetched.
+fn keymap() -> Keymap {
+    let source = match fetch("/keymap") {
+        Some(source) => source,
+        None => return Keymap::default(),
+    };
+
+    source.parse().unwrap_or_else(|error| {
+        warn!("keymap: {}", error);
+        Keymap::default()
+    })
+}
+
+/// The body of `path` on the server, fetched before the page is drawn.
+fn fetch(path: &str) -> Option<String> {
+    let request = XmlHttpRequest::new().ok()?;
+
+    request.open_with_async("GET", path, false).ok()?;
+    request.send().ok()?;
+
+    match request.status().ok()? {
+        200 => request.response_text().ok()?,
+        _ => None,
+    }
+}
+
+fn app(scope: Scope<Props>) -> Element {
+    let props = scope.props;
+
+    let context = match props.context.lock() {
+        Ok(context) => context,
+        Err(_) => return scope.render(rsx! { pre { "unavailable" } }),
+    };
+
+    // Renders again whenever the context says something on screen changed,
+    // going back to the top on focusing another document.
+    scope.use_hook(|_| {
+        let update = scope.schedule_update();
+        let mut events = context.subscribe();
+
+        scope.spawn(async move {
+            loop {
+                let reply = match events.recv().await {
+                    Ok(event) => Reply::from(&event),
+                    Err(RecvError::Lagged(_)) => Directives::REDRAW.into(),
+                    Err(RecvError::Closed) => break,
+                };
+
+                if reply.directives.contains(Directives::FOCUS_CHANGE) {
+                    if let Some(window) = web_sys::window() {
+                        window.scroll_to_with_x_and_y(0.0, 0.0);
+                    }
+                }
+
+                if reply.redraw() {
+                    update();
+                }
+            }
+        });
+    });
+
+    let frame = Frame {
+        log: props.log.lock().is_ok_and(|log| *log),
+        bindings: match props.bindings.lock().is_ok_and(|bindings| *bindings) {
+            true => props.keymap.bindings(),
+            false => vec![],
+        },
+        ..Frame::default()
+    };
+
+    let view = match context.view(&frame) {
+        Ok(view) => view,
+        Err(error) => {
+            warn!("viewing: {}", error);
+
+            return scope.render(rsx! { pre { "{context}" } });
+        }
+    };
+
+    let dirty = match view.status.dirty {
+        true => "*",
+        false => "",
+    };
+
+    let status = format!(
+        "{}{} {}:{}",
+        view.status.name,
+        dirty,
+        view.status.position.line + 1,
+        view.status.position.column + 1
+    );
+
+    let lines = view.lines.iter().map(|line| {
+        line.spans
+            .iter()
+            .map(|span| {
+                let (css, title) = look(&span.look);
+
+                (css, title, span.text.clone())
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let notes = view.panels.iter().flat_map(notes);
+
+    // Keys go through the keymap, and the commands it comes to are applied
+    // here, except for those that only change the page, which renders again
+    // by itself. Quitting is left to the terminal.
+    let onkeydown = move |event: KeyboardEvent| {
+        let key = match key(&event.data) {
+            Some(key) => key,
+            None => return,
+        };
+
+        let press = match props.chords.lock() {
+            Ok(mut chords) => props.keymap.press(&mut chords, key),
+            Err(_) => return,
+        };
+
+        let op = match press {
+            Press::Command(Command::Bindings | Command::ToggleLog) => {
+                let shown = match press {
+                    Press::Command(Command::Bindings) => &props.bindings,
+                    _ => &props.log,
+                };
+
+                if let Ok(mut shown) = shown.lock() {
+                    *shown = !*shown;
+                }
+
+                scope.needs_update();
+
+                return;
+            }
+            Press::Command(Command::PageUp | Command::PageDown) => {
+                let pages = match press {
+                    Press::Command(Command::PageUp) => -1.0,
+                    _ => 1.0,
+                };
+
+                if let Some(window) = web_sys::window() {
+                    let height = window
+                        .inner_height()
+                        .ok()
+                        .and_then(|height| height.as_f64());
+
+                    window.scroll_by_with_x_and_y(0.0, pages * height.unwrap_or_default());
+                }
+
+                return;
+            }
+            Press::Command(Command::Search) => web_sys::window()
+                .and_then(|window| window.prompt_with_message("search").ok().flatten())
+                .map(|pattern| Op::Search(Query::literal(&pattern))),
+            Press::Command(command) => match props.context.lock() {
+                Ok(context) => command.op(&context),
+                Err(_) => return,
+            },
+            Press::Unbound(keys) => match keys.as_slice() {
+                [Key {
+                    code: Code::Char(c),
+                    modifiers,
+                }] if modifiers.is_empty() => Some(Op::Type(c.to_string())),
+                _ => None,
+            },
+            Press::Pending => None,
+        };
+
+        if let (Some(op), Ok(mut context)) = (op, props.context.lock()) {
+            context.apply(op);
+        }
+    };
 
     scope.render(rsx! {
-        div { "{context}" }
+        header { "{status}" }
+        pre {
+            tabindex: "0",
+            onkeydown: onkeydown,
+            lines.map(|spans| rsx!(
+                div {
+                    style: "min-height: 1.2em;",
+                    spans.into_iter().map(|(css, title, text)| rsx!(
+                        span { style: "{css}", title: "{title}", "{text}" }
+                    ))
+                }
+            ))
+        }
+        ul {
+            notes.map(|(css, note)| rsx!(
+                li { style: "{css}", "{note}" }
+            ))
+        }
     })
 }
+
+/// The key a keyboard event is for in a keymap, if it has one.
+fn key(data: &KeyboardData) -> Option<Key> {
+    let mut chars = data.key.chars();
+
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => Code::Char(c),
+        _ => match data.key.as_str() {
+            "Enter" => Code::Enter,
+            "Escape" => Code::Esc,
+            "Backspace" => Code::Backspace,
+            "Delete" => Code::Delete,
+            "Tab" => Code::Tab,
+            "ArrowLeft" => Code::Left,
+            "ArrowRight" => Code::Right,
+            "ArrowUp" => Code::Up,
+            "ArrowDown" => Code::Down,
+            "Home" => Code::Home,
+            "End" => Code::End,
+            "PageUp" => Code::PageUp,
+            "PageDown" => Code::PageDown,
+            key => Code::F(key.strip_prefix('F')?.parse().ok()?),
+        },
+    };
+
+    let mut modifiers = Modifiers::empty();
+
+    modifiers.set(Modifiers::CONTROL, data.ctrl_key);
+    modifiers.set(Modifiers::ALT, data.alt_key);
+    modifiers.set(Modifiers::SHIFT, data.shift_key);
+
+    Some(Key::new(code, modifiers))
+}
+
+/// The css and the title of text that looks like `look`.
+fn look(look: &Look) -> (String, String) {
+    let mut css = String::new();
+    let mut title = String::new();
+
+    if look.selected {
+        css.push_str("background: #99c1f1;");
+    }
+
+    if look.matched {
+        css.push_str("outline: 1px solid #e5a50a;");
+    }
+
+    for style in &look.styles {
+        match style {
+            Style::Bold => css.push_str("font-weight: bold;"),
+            Style::Italic => css.push_str("font-style: italic;"),
+            Style::Underline => css.push_str("text-decoration: underline;"),
+            Style::Highlight => css.push_str("background: #fce788;"),
+            Style::Link(url) => {
+                css.push_str("color: #1a5fb4; text-decoration: underline;");
+                title = url.clone();
+            }
+            Style::Diagnostic(message) => {
+                css.push_str("text-decoration: red wavy underline;");
+                title = message.clone();
+            }
+        }
+    }
+
+    (css, title)
+}
+
+/// The lines of a panel, each with its css.
+fn notes(panel: &Panel) -> Vec<(String, String)> {
+    let color = |severity| match severity {
+        Severity::Info => "color: #5e5c64;".to_string(),
+        Severity::Warning => "color: #c64600;".to_string(),
+        Severity::Error => "color: #c01c28;".to_string(),
+    };
+
+    match panel {
+        Panel::Bindings(bindings) => bindings
+            .iter()
+            .map(|binding| {
+                (
+                    String::new(),
+                    format!("{} {}", binding.keys, binding.command),
+                )
+            })
+            .collect(),
+        Panel::Log(notes) | Panel::Alerts(notes) => notes
+            .iter()
+            .map(|note| (color(note.severity), note.to_string()))
+            .collect(),
+        Panel::Diagnostics(messages) => messages
+            .iter()
+            .map(|message| (color(Severity::Error), message.clone()))
+            .collect(),
+        Panel::Conflict => vec![(
+            color(Severity::Warning),
+            "changed on disk while it has unsaved edits".into(),
+        )],
+        Panel::Search(query) => vec![(String::new(), format!("/{}", query.pattern))],
+        Panel::Documents(tabs) => {
+            let tabs = tabs.iter().map(|tab| {
+                let dirty = match tab.dirty {
+                    true => "*",
+                    false => "",
+                };
+
+                match tab.current {
+                    true => format!("[{}{}]", tab.name, dirty),
+                    false => format!("{}{}", tab.name, dirty),
+                }
+            });
+
+            vec![(String::new(), tabs.collect::<Vec<_>>().join(" "))]
+        }
+    }
+}
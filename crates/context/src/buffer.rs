@@ -1,38 +1,1518 @@
+pub(crate) mod de;
+mod diff;
+#[cfg(test)]
+mod fixtures;
+mod history;
+mod index;
+mod position;
+mod search;
+mod selection;
+mod style;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Serialize {0}")]
     Serialize(String),
+    #[error("Key {0}")]
+    Key(String),
+    #[error("Deserialize {line}:{column} {message}")]
+    Deserialize {
+        line: usize,
+        column: usize,
+        message: String,
+    },
     #[error("CursorLock")]
     CursorLock,
+    #[error("MarkLock")]
+    MarkLock,
+    #[error("StyleLock")]
+    StyleLock,
+    #[error("HistoryLock")]
+    HistoryLock,
+    #[error("JournalLock")]
+    JournalLock,
+    #[error("Invalid {0}")]
+    Invalid(String),
+    #[error("NoMark {0}")]
+    NoMark(String),
+    #[error("NoLine {line} of {count}")]
+    NoLine { line: usize, count: usize },
     #[error("BufferLock")]
     BufferLock,
+    #[error("OutOfRange {start}..{end} of {length}")]
+    OutOfRange {
+        start: usize,
+        end: usize,
+        length: usize,
+    },
+    #[error("Dot {0}")]
+    Dot(#[from] DotRange<Actor>),
+    #[error("Pattern {0}")]
+    Pattern(#[from] regex::Error),
+}
+
+use crate::{notify, BACKLOG};
+
+use ::{
+    crdts::{list, map, CmRDT, Dot, DotRange, List, MVReg, Map, VClock},
+    serde::{
+        de::DeserializeOwned,
+        ser::{self, Serializer},
+        Deserialize, Serialize,
+    },
+    std::{
+        fmt::{self, Display},
+        ops::Range,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex, MutexGuard,
+        },
+    },
+    tokio::sync::broadcast,
+    unicode_segmentation::UnicodeSegmentation,
+    unicode_width::UnicodeWidthStr,
+};
+
+pub(crate) use de::from_str;
+
+use history::{Group, History, Run, Transaction};
+pub use index::Anchor;
+use index::{Id, Index};
+pub use position::Position;
+pub use search::{Case, Direction, Query};
+pub use selection::{Selection, Span};
+pub use style::{Annotation, Style, Styled, Tag};
+
+pub type Actor = u8;
+
+pub type Cursor = usize;
+
+/// Each actor owns a register of their own selections, the last being the
+/// primary one that single cursor operations act on.
+type Cursors = Map<Actor, MVReg<Vec<Span>, Actor>, Actor>;
+
+type Marks = Map<String, MVReg<Anchor, Actor>, Actor>;
+
+/// Styled spans, each in a register under the tag it was made with.
+type Styles = Map<Tag, MVReg<Styled, Actor>, Actor>;
+
+/// Everything that changes a `Buffer`, whether made here or received from
+/// another actor.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Op {
+    List(list::Op<char, Actor>),
+    Cursors(map::Op<Actor, MVReg<Vec<Span>, Actor>, Actor>),
+    Marks(map::Op<String, MVReg<Anchor, Actor>, Actor>),
+    Styles(map::Op<Tag, MVReg<Styled, Actor>, Actor>),
+}
+
+/// What an applied op did, sent to whoever subscribed to the buffer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Change {
+    /// The chars in `deleted` went and `inserted` new ones took their place.
+    /// Offsets are into the text as it was just before.
+    Edited {
+        deleted: Range<usize>,
+        inserted: usize,
+    },
+    Selected,
+    Marked,
+    Styled,
+    /// Stands in for changes a subscriber fell too far behind on, so it is
+    /// all to be read again.
+    Reset,
+}
+
+/// The full state of a `Buffer`, from which it can be rebuilt as it was. The
+/// list is kept as its elements rather than as a `List`, whose identifier
+/// keyed map not every format can hold.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    elements: Vec<(Id, char)>,
+    clock: VClock<Actor>,
+    cursors: Cursors,
+    marks: Marks,
+    #[serde(default)]
+    styles: Styles,
+}
+
+#[derive(Clone)]
+pub struct Buffer {
+    buffer: Arc<Mutex<List<char, Actor>>>,
+    index: Arc<Mutex<Index>>,
+    cursors: Arc<Mutex<Cursors>>,
+    marks: Arc<Mutex<Marks>>,
+    styles: Arc<Mutex<Styles>>,
+    history: Arc<Mutex<History>>,
+    journal: Arc<Mutex<Vec<Op>>>,
+    revision: Arc<AtomicUsize>,
+    changes: broadcast::Sender<Change>,
+    layout: Layout,
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Self {
+            buffer: Arc::new(Mutex::new(List::new())),
+            index: Arc::new(Mutex::new(Index::default())),
+            cursors: Arc::new(Mutex::new(Map::new())),
+            marks: Arc::new(Mutex::new(Map::new())),
+            styles: Arc::new(Mutex::new(Map::new())),
+            history: Arc::new(Mutex::new(History::default())),
+            journal: Arc::new(Mutex::new(vec![])),
+            revision: Arc::new(AtomicUsize::new(0)),
+            changes: broadcast::channel(BACKLOG).0,
+            layout: Layout::default(),
+        }
+    }
+
+    /// Counts the changes to the text, for what is worked out from it to be
+    /// kept until the next one.
+    pub fn revision(&self) -> usize {
+        self.revision.load(Ordering::Relaxed)
+    }
+
+    fn index(&self) -> Result<MutexGuard<'_, Index>, Error> {
+        self.index.lock().map_err(|_| Error::BufferLock)
+    }
+
+    fn history(&self) -> Result<MutexGuard<'_, History>, Error> {
+        self.history.lock().map_err(|_| Error::HistoryLock)
+    }
+
+    pub fn len(&self) -> Result<usize, Error> {
+        Ok(self.index()?.len())
+    }
+
+    pub fn is_empty(&self) -> Result<bool, Error> {
+        self.len().map(|length| length == 0)
+    }
+
+    pub fn read(&self) -> Result<Vec<char>, Error> {
+        let buffer = self.buffer.lock().map_err(|_| Error::BufferLock)?;
+
+        Ok(buffer.iter().copied().collect())
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> Result<String, Error> {
+        self.check(&range)?;
+
+        Ok(self.index()?.slice(range))
+    }
+
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        de::from_str(&self.to_string())
+    }
+
+    pub fn check(&self, range: &Range<usize>) -> Result<(), Error> {
+        let length = self.len()?;
+
+        if range.start > range.end || range.end > length {
+            return Err(Error::OutOfRange {
+                start: range.start,
+                end: range.end,
+                length,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn insert(&mut self, index: usize, text: &str, actor: Actor) -> Result<(), Error> {
+        self.check(&(index..index))?;
+
+        for (offset, value) in text.chars().enumerate() {
+            let op = self.index()?.insert_op(index + offset, value, actor);
+
+            if let list::Op::Insert { id, .. } = &op {
+                self.history()?.inserted(actor, id.clone());
+            }
+
+            self.commit(op)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn delete(&mut self, range: Range<usize>, actor: Actor) -> Result<(), Error> {
+        self.check(&range)?;
+
+        let mut run = vec![];
+
+        for _ in range.clone() {
+            let op = self.index()?.delete_op(range.start, actor);
+
+            if let Some(op) = op {
+                if let list::Op::Delete { id, .. } = &op {
+                    if let Some(value) = self.index()?.get(id) {
+                        run.push((id.clone(), value));
+                    }
+                }
+
+                self.commit(op)?;
+            }
+        }
+
+        self.history()?.deleted(actor, run);
+
+        Ok(())
+    }
+
+    pub fn replace(&mut self, range: Range<usize>, text: &str, actor: Actor) -> Result<(), Error> {
+        let index = range.start;
+
+        self.delete(range, actor)?;
+        self.insert(index, text, actor)
+    }
+
+    /// Turns the text into `text` with only the inserts and deletes a diff
+    /// finds, so selections and marks on the parts left alone stay put.
+    pub fn import(&mut self, text: &str, actor: Actor) -> Result<(), Error> {
+        self.transact(actor, |buffer| buffer.adopt(text, actor))
+    }
+
+    /// Imports `text` outside of `actor`'s history, for a change made
+    /// elsewhere that undoing shouldn't revert.
+    pub fn adopt(&mut self, text: &str, actor: Actor) -> Result<(), Error> {
+        let hunks = diff::diff(&self.to_string(), text);
+
+        for hunk in hunks.into_iter().rev() {
+            self.replace(hunk.delete, &hunk.insert, actor)?;
+        }
+
+        self.history()?.moved(actor);
+
+        Ok(())
+    }
+}
+
+impl Buffer {
+    /// Lines are what the newlines split the text into, so there is always
+    /// at least one, and one more after a trailing newline.
+    pub fn line_count(&self) -> Result<usize, Error> {
+        Ok(self.index()?.line_count())
+    }
+
+    /// Chars of `line`, without its newline.
+    pub fn line_span(&self, line: usize) -> Result<Range<usize>, Error> {
+        let index = self.index()?;
+
+        let start = index.line_start(line).ok_or(Error::NoLine {
+            line,
+            count: index.line_count(),
+        })?;
+
+        let end = match index.line_start(line + 1) {
+            Some(next) => next - 1,
+            None => index.len(),
+        };
+
+        Ok(start..end)
+    }
+
+    pub fn line(&self, line: usize) -> Result<String, Error> {
+        let span = self.line_span(line)?;
+
+        Ok(self.index()?.slice(span))
+    }
+
+    /// The lines in `range` that exist, for showing part of the text.
+    pub fn lines(&self, range: Range<usize>) -> Result<Vec<String>, Error> {
+        let count = self.line_count()?;
+
+        (range.start.min(count)..range.end.min(count))
+            .map(|line| self.line(line))
+            .collect()
+    }
+
+    pub fn offset_to_position(&self, offset: usize) -> Result<Position, Error> {
+        self.check(&(offset..offset))?;
+
+        let index = self.index()?;
+
+        let line = index.line_of(offset);
+        let start = index.line_start(line).unwrap_or(0);
+
+        Ok(Position::new(line, offset - start))
+    }
+
+    /// Columns past the end of the line land on its end, so moving between
+    /// lines of different lengths keeps to the text.
+    pub fn position_to_offset(&self, position: Position) -> Result<usize, Error> {
+        let span = self.line_span(position.line)?;
+
+        Ok((span.start + position.column).min(span.end))
+    }
+
+    pub fn slice_positions(&self, range: Range<Position>) -> Result<String, Error> {
+        let start = self.position_to_offset(range.start)?;
+        let end = self.position_to_offset(range.end)?;
+
+        self.slice(start..end)
+    }
+}
+
+/// Columns a terminal gives `text`, counting each grapheme cluster as a unit
+/// so emoji sequences and combining marks don't over or under count.
+pub fn width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+fn grapheme_width(grapheme: &str) -> usize {
+    if grapheme.contains('\u{fe0f}') {
+        2
+    } else {
+        grapheme.width().min(2)
+    }
+}
+
+impl Buffer {
+    /// Char offsets of every grapheme cluster boundary, including both ends.
+    fn boundaries(&self) -> Result<Vec<usize>, Error> {
+        let text = self.to_string();
+
+        let mut offset = 0;
+        let mut boundaries = vec![0];
+
+        for grapheme in text.graphemes(true) {
+            offset += grapheme.chars().count();
+            boundaries.push(offset);
+        }
+
+        Ok(boundaries)
+    }
+
+    pub fn grapheme_count(&self) -> Result<usize, Error> {
+        Ok(self.boundaries()?.len() - 1)
+    }
+
+    pub fn grapheme(&self, index: usize) -> Result<Option<String>, Error> {
+        Ok(self
+            .to_string()
+            .graphemes(true)
+            .nth(index)
+            .map(String::from))
+    }
+
+    pub fn grapheme_offset(&self, index: usize) -> Result<usize, Error> {
+        let boundaries = self.boundaries()?;
+
+        Ok(boundaries[index.min(boundaries.len() - 1)])
+    }
+
+    pub fn grapheme_index(&self, offset: usize) -> Result<usize, Error> {
+        let boundaries = self.boundaries()?;
+
+        Ok(boundaries.partition_point(|boundary| *boundary <= offset) - 1)
+    }
+
+    /// Boundaries of the line holding `offset`, trailing newline included.
+    /// Clusters never span a newline, so this agrees with `boundaries`
+    /// without reading the rest of the document.
+    fn line_boundaries(&self, offset: usize) -> Result<Vec<usize>, Error> {
+        let line = self.line_range(offset)?;
+
+        let mut boundary = line.start;
+        let mut boundaries = vec![line.start];
+
+        for grapheme in self.index()?.slice(line).graphemes(true) {
+            boundary += grapheme.chars().count();
+            boundaries.push(boundary);
+        }
+
+        Ok(boundaries)
+    }
+
+    /// Chars of the line holding `offset`, trailing newline included.
+    fn line_range(&self, offset: usize) -> Result<Range<usize>, Error> {
+        let index = self.index()?;
+
+        let line = index.line_of(offset);
+        let start = index.line_start(line).unwrap_or(0);
+        let end = index.line_start(line + 1).unwrap_or_else(|| index.len());
+
+        Ok(start..end)
+    }
+
+    /// The word touching `offset`, preferring the one before it.
+    fn word(&self, offset: usize) -> Result<Option<Range<usize>>, Error> {
+        let line = self.line_range(offset)?;
+        let text = self.index()?.slice(line.clone());
+
+        let mut start = line.start;
+
+        for segment in text.split_word_bounds() {
+            let end = start + segment.chars().count();
+
+            if (start..=end).contains(&offset) && segment.chars().any(char::is_alphanumeric) {
+                return Ok(Some(start..end));
+            }
+
+            start = end;
+        }
+
+        Ok(None)
+    }
+
+    pub fn next_boundary(&self, offset: usize) -> Result<usize, Error> {
+        Ok(self
+            .line_boundaries(offset)?
+            .into_iter()
+            .find(|boundary| *boundary > offset)
+            .unwrap_or(offset))
+    }
+
+    pub fn previous_boundary(&self, offset: usize) -> Result<usize, Error> {
+        if offset == 0 {
+            return Ok(0);
+        }
+
+        Ok(self
+            .line_boundaries(offset - 1)?
+            .into_iter()
+            .rev()
+            .find(|boundary| *boundary < offset)
+            .unwrap_or(0))
+    }
+
+    /// The grapheme boundary at or before `offset`.
+    fn snap(&self, offset: usize) -> Result<usize, Error> {
+        let offset = offset.min(self.len()?);
+
+        Ok(self
+            .line_boundaries(offset)?
+            .into_iter()
+            .rev()
+            .find(|boundary| *boundary <= offset)
+            .unwrap_or(0))
+    }
+
+    pub fn actors(&self) -> Result<Vec<Actor>, Error> {
+        let cursors = self.cursors.lock().map_err(|_| Error::CursorLock)?;
+
+        Ok(cursors.keys().map(|key| *key.val).collect())
+    }
+
+    /// Where `actor`'s selections sit in the text as it reads now. Everyone
+    /// starts out with a single cursor at the start.
+    pub fn selections(&self, actor: Actor) -> Result<Vec<Selection>, Error> {
+        let spans = self.spans(actor)?;
+
+        let index = self.index()?;
+
+        Ok(spans
+            .iter()
+            .map(|span| Selection {
+                anchor: index.resolve(&span.anchor),
+                head: index.resolve(&span.head),
+            })
+            .collect())
+    }
+
+    fn spans(&self, actor: Actor) -> Result<Vec<Span>, Error> {
+        let cursors = self.cursors.lock().map_err(|_| Error::CursorLock)?;
+
+        Ok(cursors
+            .get(&actor)
+            .val
+            .and_then(|register| register.read().val.into_iter().max())
+            .filter(|spans| !spans.is_empty())
+            .unwrap_or_else(|| vec![Span::default()]))
+    }
+
+    fn set_spans(&mut self, spans: Vec<Span>, actor: Actor) -> Result<(), Error> {
+        self.history()?.moved(actor);
+
+        let op = {
+            let cursors = self.cursors.lock().map_err(|_| Error::CursorLock)?;
+
+            cursors.update(
+                actor,
+                cursors.read_ctx().derive_add_ctx(actor),
+                |register, context| register.write(spans, context),
+            )
+        };
+
+        self.apply(Op::Cursors(op));
+
+        Ok(())
+    }
+
+    pub fn selection(&self, actor: Actor) -> Result<Selection, Error> {
+        Ok(self.selections(actor)?.pop().unwrap_or_default())
+    }
+
+    pub fn cursor(&self, actor: Actor) -> Result<Cursor, Error> {
+        Ok(self.selection(actor)?.head)
+    }
+
+    /// Replaces every selection `actor` has, snapping both ends to grapheme
+    /// boundaries and merging any that overlap.
+    pub fn set_selections(
+        &mut self,
+        selections: Vec<Selection>,
+        actor: Actor,
+    ) -> Result<(), Error> {
+        let selections = selections
+            .into_iter()
+            .map(|selection| {
+                Ok(Selection {
+                    anchor: self.snap(selection.anchor)?,
+                    head: self.snap(selection.head)?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let spans = {
+            let index = self.index()?;
+
+            selection::normalize(selections)
+                .into_iter()
+                .map(|selection| Span {
+                    anchor: index.anchor(selection.anchor),
+                    head: index.anchor(selection.head),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        self.set_spans(spans, actor)
+    }
+
+    pub fn select(&mut self, range: Range<usize>, actor: Actor) -> Result<(), Error> {
+        self.set_selections(vec![Selection::from(range)], actor)
+    }
+
+    /// Places a single cursor on the grapheme boundary at or before `cursor`.
+    pub fn set_cursor(&mut self, cursor: Cursor, actor: Actor) -> Result<(), Error> {
+        self.set_selections(vec![Selection::caret(cursor)], actor)
+    }
+
+    fn travel(&self, mut cursor: Cursor, graphemes: isize) -> Result<Cursor, Error> {
+        for _ in 0..graphemes.unsigned_abs() {
+            let next = if graphemes < 0 {
+                self.previous_boundary(cursor)?
+            } else {
+                self.next_boundary(cursor)?
+            };
+
+            if next == cursor {
+                break;
+            }
+
+            cursor = next;
+        }
+
+        Ok(cursor)
+    }
+
+    /// Moves every caret by `graphemes`. A selection collapses to the side
+    /// being moved towards instead.
+    pub fn step(&mut self, graphemes: isize, actor: Actor) -> Result<(), Error> {
+        let selections = self
+            .selections(actor)?
+            .into_iter()
+            .map(|selection| match selection.is_empty() {
+                true => self.travel(selection.head, graphemes).map(Selection::caret),
+                false if graphemes < 0 => Ok(Selection::caret(selection.start())),
+                false => Ok(Selection::caret(selection.end())),
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        self.set_selections(selections, actor)
+    }
+
+    /// Moves the head of every selection by `graphemes`, leaving anchors put.
+    pub fn extend(&mut self, graphemes: isize, actor: Actor) -> Result<(), Error> {
+        let selections = self
+            .selections(actor)?
+            .into_iter()
+            .map(|selection| {
+                Ok(Selection {
+                    anchor: selection.anchor,
+                    head: self.travel(selection.head, graphemes)?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        self.set_selections(selections, actor)
+    }
+
+    pub fn collapse(&mut self, actor: Actor) -> Result<(), Error> {
+        let selections = self
+            .selections(actor)?
+            .iter()
+            .map(Selection::collapse)
+            .collect();
+
+        self.set_selections(selections, actor)
+    }
+
+    /// Runs `edit` against each of `actor`'s selections in order, shifting
+    /// every selection by what the edits before it added or removed, and
+    /// leaves a cursor after each replacement.
+    fn edit<F>(&mut self, actor: Actor, group: Group, mut edit: F) -> Result<(), Error>
+    where
+        F: FnMut(&Self, usize, Selection) -> Result<(Range<usize>, String), Error>,
+    {
+        self.group(actor, group, |buffer| {
+            let mut shift = 0isize;
+            let mut cursors = vec![];
+
+            for (ordinal, selection) in buffer.selections(actor)?.into_iter().enumerate() {
+                let selection = Selection {
+                    anchor: selection.anchor.saturating_add_signed(shift),
+                    head: selection.head.saturating_add_signed(shift),
+                };
+
+                let (range, text) = edit(buffer, ordinal, selection)?;
+
+                let length = text.chars().count();
+
+                buffer.replace(range.clone(), &text, actor)?;
+
+                cursors.push(Selection::caret(range.start + length));
+
+                shift += length as isize - range.len() as isize;
+            }
+
+            buffer.set_selections(cursors, actor)
+        })
+    }
+
+    /// Replaces every selection with `text`.
+    pub fn write(&mut self, text: &str, actor: Actor) -> Result<(), Error> {
+        let group = if text.contains('\n') {
+            Group::Edit
+        } else if text.chars().all(char::is_whitespace) {
+            Group::Space
+        } else {
+            Group::Type
+        };
+
+        self.edit(actor, group, |_, _, selection| {
+            Ok((selection.range(), text.into()))
+        })
+    }
+
+    /// Like `write`, except that when `text` has one line per selection each
+    /// selection gets its own line.
+    pub fn paste(&mut self, text: &str, actor: Actor) -> Result<(), Error> {
+        let lines = text.lines().collect::<Vec<_>>();
+
+        let distribute = lines.len() > 1 && lines.len() == self.selections(actor)?.len();
+
+        self.edit(
+            actor,
+            Group::Edit,
+            |_, ordinal, selection| match distribute {
+                true => Ok((selection.range(), lines[ordinal].into())),
+                false => Ok((selection.range(), text.into())),
+            },
+        )
+    }
+
+    pub fn backspace(&mut self, actor: Actor) -> Result<(), Error> {
+        self.edit(actor, Group::Delete, |buffer, _, selection| {
+            let range = match selection.is_empty() {
+                true => buffer.previous_boundary(selection.head)?..selection.head,
+                false => selection.range(),
+            };
+
+            Ok((range, String::new()))
+        })
+    }
+
+    pub fn delete_forward(&mut self, actor: Actor) -> Result<(), Error> {
+        self.edit(actor, Group::Delete, |buffer, _, selection| {
+            let range = match selection.is_empty() {
+                true => selection.head..buffer.next_boundary(selection.head)?,
+                false => selection.range(),
+            };
+
+            Ok((range, String::new()))
+        })
+    }
+
+    /// Selects the word under the last cursor, or once something is
+    /// selected, adds a selection on the next place the same text appears.
+    pub fn select_next(&mut self, actor: Actor) -> Result<(), Error> {
+        let mut selections = self.selections(actor)?;
+
+        let last = selections.pop().unwrap_or_default();
+
+        if last.is_empty() {
+            if let Some(word) = self.word(last.head)? {
+                selections.push(Selection::from(word));
+            } else {
+                selections.push(last);
+            }
+
+            return self.set_selections(selections, actor);
+        }
+
+        selections.push(last);
+
+        let text = self.read()?;
+        let needle = &text[last.range()];
+
+        let taken = |start: usize| {
+            selections
+                .iter()
+                .any(|selection| selection.start() == start)
+        };
+
+        let found = (last.end()..text.len())
+            .chain(0..last.end())
+            .filter(|start| text[*start..].starts_with(needle))
+            .find(|start| !taken(*start));
+
+        if let Some(start) = found {
+            selections.push(Selection::from(start..start + needle.len()));
+        }
+
+        self.set_selections(selections, actor)
+    }
+
+    /// Breaks each selection into one selection per line it covers.
+    pub fn split_lines(&mut self, actor: Actor) -> Result<(), Error> {
+        let mut selections = vec![];
+
+        for selection in self.selections(actor)? {
+            let index = self.index()?;
+
+            let first = index.line_of(selection.start());
+            let last = index.line_of(selection.end());
+
+            if first == last {
+                selections.push(selection);
+                continue;
+            }
+
+            for line in first..=last {
+                let start = index.line_start(line).unwrap_or(0);
+
+                let end = index
+                    .line_start(line + 1)
+                    .map(|next| next - 1)
+                    .unwrap_or_else(|| index.len());
+
+                if line == last && selection.end() == start {
+                    break;
+                }
+
+                selections.push(Selection::from(
+                    selection.start().max(start)..selection.end().min(end),
+                ));
+            }
+        }
+
+        self.set_selections(selections, actor)
+    }
+}
+
+impl Buffer {
+    /// Where `query` matches, as char ranges in order.
+    pub fn search(&self, query: &Query) -> Result<Vec<Range<usize>>, Error> {
+        let regex = query.compile()?;
+        let text = self.to_string();
+
+        let found = search::matches(query, &regex, &text);
+
+        Ok(found.into_iter().map(|found| found.range).collect())
+    }
+
+    /// The first match after `from`, or the last one before it going
+    /// backward, wrapping around the end of the text.
+    pub fn find(
+        &self,
+        query: &Query,
+        from: usize,
+        direction: Direction,
+    ) -> Result<Option<Range<usize>>, Error> {
+        let matches = self.search(query)?;
+
+        let found = match direction {
+            Direction::Forward => matches
+                .iter()
+                .find(|range| range.start >= from)
+                .or_else(|| matches.first()),
+            Direction::Backward => matches
+                .iter()
+                .rev()
+                .find(|range| range.end <= from)
+                .or_else(|| matches.last()),
+        };
+
+        Ok(found.cloned())
+    }
+
+    /// Selects the match past the primary selection, returning whether
+    /// there was one.
+    pub fn find_next(
+        &mut self,
+        query: &Query,
+        direction: Direction,
+        actor: Actor,
+    ) -> Result<bool, Error> {
+        let selection = self.selection(actor)?;
+
+        let from = match direction {
+            Direction::Forward => selection.end(),
+            Direction::Backward => selection.start(),
+        };
+
+        match self.find(query, from, direction)? {
+            Some(range) => self.select(range, actor).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Replaces the primary selection if it is a match and selects the next
+    /// one, returning whether anything was replaced.
+    pub fn replace_next(
+        &mut self,
+        query: &Query,
+        replacement: &str,
+        actor: Actor,
+    ) -> Result<bool, Error> {
+        let regex = query.compile()?;
+        let text = self.to_string();
+
+        let selected = self.selection(actor)?.range();
+
+        let found = search::matches(query, &regex, &text)
+            .into_iter()
+            .find(|found| found.range == selected);
+
+        let found = match found {
+            Some(found) => found,
+            None => {
+                return self
+                    .find_next(query, Direction::Forward, actor)
+                    .map(|_| false)
+            }
+        };
+
+        let replacement = search::expand(query, &regex, &text, &found, replacement);
+        let end = found.range.start + replacement.chars().count();
+
+        self.transact(actor, |buffer| {
+            buffer.replace(found.range, &replacement, actor)?;
+            buffer.set_cursor(end, actor)
+        })?;
+
+        self.find_next(query, Direction::Forward, actor)?;
+
+        Ok(true)
+    }
+
+    /// Replaces every match in one undo step, returning how many there were.
+    pub fn replace_all(
+        &mut self,
+        query: &Query,
+        replacement: &str,
+        actor: Actor,
+    ) -> Result<usize, Error> {
+        let regex = query.compile()?;
+        let text = self.to_string();
+
+        let found = search::matches(query, &regex, &text);
+
+        if found.is_empty() {
+            return Ok(0);
+        }
+
+        self.transact(actor, |buffer| {
+            for found in found.iter().rev() {
+                let replacement = search::expand(query, &regex, &text, found, replacement);
+
+                buffer.replace(found.range.clone(), &replacement, actor)?;
+            }
+
+            Ok(found.len())
+        })
+    }
+}
+
+impl Buffer {
+    /// Runs `f` as a single step in `actor`'s undo history.
+    pub fn transact<T, F>(&mut self, actor: Actor, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        self.group(actor, Group::Edit, f)
+    }
+
+    fn group<T, F>(&mut self, actor: Actor, group: Group, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        let spans = self.spans(actor)?;
+
+        self.history()?.begin(actor, group, spans);
+
+        let result = f(self);
+
+        self.history()?.end(actor);
+
+        result
+    }
+
+    /// Reverts `actor`'s last transaction, returning whether there was one.
+    pub fn undo(&mut self, actor: Actor) -> Result<bool, Error> {
+        let transaction = self.history()?.undo(actor);
+
+        match transaction {
+            Some(transaction) => {
+                let inverse = self.revert(transaction, actor)?;
+
+                self.history()?.undone(actor, inverse);
+
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn redo(&mut self, actor: Actor) -> Result<bool, Error> {
+        let transaction = self.history()?.redo(actor);
+
+        match transaction {
+            Some(transaction) => {
+                let inverse = self.revert(transaction, actor)?;
+
+                self.history()?.redone(actor, inverse);
+
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Forgets every actor's history, as when the whole text is replaced.
+    pub fn forget(&mut self) -> Result<(), Error> {
+        self.history()?.clear();
+
+        Ok(())
+    }
+
+    /// Removes what `transaction` inserted and restores what it deleted,
+    /// returning the transaction that would put it back. Restored elements
+    /// go wherever their old identifiers sort among the current ones, so
+    /// edits others made in the meantime stay where they are.
+    fn revert(&mut self, transaction: Transaction, actor: Actor) -> Result<Transaction, Error> {
+        let mut inverse = Transaction {
+            selections: self.spans(actor)?,
+            ..Transaction::default()
+        };
+
+        let removed = {
+            let history = self.history()?;
+            let index = self.index()?;
+
+            let mut removed = transaction
+                .inserted
+                .into_iter()
+                .map(|id| history.resolve(actor, id))
+                .filter_map(|id| index.get(&id).map(|value| (index.rank(&id), id, value)))
+                .collect::<Vec<_>>();
+
+            removed.sort();
+            removed
+        };
+
+        let mut previous = None;
+
+        for (rank, id, value) in removed {
+            let op = self.index()?.delete_id_op(&id, actor);
+
+            self.commit(op)?;
+
+            match inverse.deleted.last_mut() {
+                Some(run) if previous == Some(rank.wrapping_sub(1)) => run.push((id, value)),
+                _ => inverse.deleted.push(vec![(id, value)]),
+            }
+
+            previous = Some(rank);
+        }
+
+        let mut restored = {
+            let history = self.history()?;
+            let index = self.index()?;
+
+            transaction
+                .deleted
+                .into_iter()
+                .map(|run| {
+                    run.into_iter()
+                        .map(|(id, value)| (history.resolve(actor, id), value))
+                        .filter(|(id, _)| !index.contains(id))
+                        .collect::<Run>()
+                })
+                .filter_map(|run| Some((index.rank(&run.first()?.0), run)))
+                .collect::<Vec<_>>()
+        };
+
+        restored.sort();
+
+        let mut shift = 0;
+
+        for (rank, run) in restored {
+            for (id, value) in run {
+                let op = self.index()?.insert_op(rank + shift, value, actor);
+
+                if let list::Op::Insert { id: restored, .. } = &op {
+                    self.history()?.alias(actor, id, restored.clone());
+
+                    inverse.inserted.insert(restored.clone());
+                }
+
+                self.commit(op)?;
+
+                shift += 1;
+            }
+        }
+
+        let spans = {
+            let history = self.history()?;
+
+            transaction
+                .selections
+                .into_iter()
+                .map(|span| Span {
+                    anchor: span.anchor.map(|id| history.resolve(actor, id)),
+                    head: span.head.map(|id| history.resolve(actor, id)),
+                })
+                .collect()
+        };
+
+        self.set_spans(spans, actor)?;
+
+        Ok(inverse)
+    }
 }
 
-use ::{
-    crdts::{CmRDT, List, MVReg},
-    serde::ser::{self, Serialize, Serializer},
-    std::{
-        fmt::Display,
-        sync::{Arc, Mutex},
-    },
-};
+impl Buffer {
+    pub fn mark(&self, name: &str) -> Result<Option<Cursor>, Error> {
+        let anchor = {
+            let marks = self.marks.lock().map_err(|_| Error::MarkLock)?;
+
+            marks
+                .get(&name.to_string())
+                .val
+                .and_then(|register| register.read().val.into_iter().max())
+        };
+
+        let index = self.index()?;
+
+        Ok(anchor.map(|anchor| index.resolve(&anchor)))
+    }
+
+    pub fn marks(&self) -> Result<Vec<(String, Cursor)>, Error> {
+        let anchors = {
+            let marks = self.marks.lock().map_err(|_| Error::MarkLock)?;
+
+            marks
+                .iter()
+                .filter_map(|entry| {
+                    let (name, register) = entry.val;
+
+                    register
+                        .read()
+                        .val
+                        .into_iter()
+                        .max()
+                        .map(|anchor| (name.clone(), anchor))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let index = self.index()?;
+
+        Ok(anchors
+            .into_iter()
+            .map(|(name, anchor)| (name, index.resolve(&anchor)))
+            .collect())
+    }
+
+    pub fn set_mark(&mut self, name: &str, cursor: Cursor, actor: Actor) -> Result<(), Error> {
+        let anchor = {
+            let cursor = self.snap(cursor)?;
+
+            self.index()?.anchor(cursor)
+        };
+
+        let op = {
+            let marks = self.marks.lock().map_err(|_| Error::MarkLock)?;
+
+            marks.update(
+                name,
+                marks.read_ctx().derive_add_ctx(actor),
+                |register, context| register.write(anchor, context),
+            )
+        };
+
+        self.apply(Op::Marks(op));
+
+        Ok(())
+    }
+
+    pub fn remove_mark(&mut self, name: &str) -> Result<(), Error> {
+        let op = {
+            let marks = self.marks.lock().map_err(|_| Error::MarkLock)?;
+
+            marks.rm(name, marks.get(&name.to_string()).derive_rm_ctx())
+        };
+
+        self.apply(Op::Marks(op));
+
+        Ok(())
+    }
+
+    /// Moves `actor` to the mark called `name`.
+    pub fn jump(&mut self, name: &str, actor: Actor) -> Result<(), Error> {
+        let cursor = self.mark(name)?.ok_or_else(|| Error::NoMark(name.into()))?;
+
+        self.set_cursor(cursor, actor)
+    }
+}
+
+impl Buffer {
+    fn styles(&self) -> Result<MutexGuard<'_, Styles>, Error> {
+        self.styles.lock().map_err(|_| Error::StyleLock)
+    }
+
+    /// Gives `range` the `style`, returning the tag naming the new span.
+    pub fn annotate(
+        &mut self,
+        range: Range<usize>,
+        style: Style,
+        actor: Actor,
+    ) -> Result<Tag, Error> {
+        self.check(&range)?;
+
+        let styled = {
+            let index = self.index()?;
+
+            Styled {
+                start: index.edge(range.start, true),
+                end: index.edge(range.end, style.expands()),
+                style,
+            }
+        };
+
+        let (tag, op) = {
+            let styles = self.styles()?;
+            let context = styles.read_ctx().derive_add_ctx(actor);
+
+            let tag = Tag {
+                actor: context.dot.actor,
+                counter: context.dot.counter,
+            };
+
+            let op = styles.update(tag, context, |register, context| {
+                register.write(styled, context)
+            });
+
+            (tag, op)
+        };
+
+        self.apply(Op::Styles(op));
+
+        Ok(tag)
+    }
+
+    /// Every span that still covers some text, in order of where it starts.
+    pub fn annotations(&self) -> Result<Vec<Annotation>, Error> {
+        let styled = self
+            .styles()?
+            .iter()
+            .filter_map(|entry| {
+                let (tag, register) = entry.val;
+
+                register
+                    .read()
+                    .val
+                    .into_iter()
+                    .max()
+                    .map(|styled| (*tag, styled))
+            })
+            .collect::<Vec<_>>();
+
+        let index = self.index()?;
+
+        let mut annotations = styled
+            .into_iter()
+            .map(|(tag, styled)| Annotation {
+                tag,
+                range: index.resolve_edge(&styled.start)..index.resolve_edge(&styled.end),
+                style: styled.style,
+            })
+            .filter(|annotation| !annotation.range.is_empty())
+            .collect::<Vec<_>>();
+
+        annotations.sort_by_key(|annotation| (annotation.range.start, annotation.tag));
+
+        Ok(annotations)
+    }
+
+    /// The styles of the spans covering `offset`.
+    pub fn styles_at(&self, offset: usize) -> Result<Vec<Style>, Error> {
+        Ok(self
+            .annotations()?
+            .into_iter()
+            .filter(|annotation| annotation.range.contains(&offset))
+            .map(|annotation| annotation.style)
+            .collect())
+    }
+
+    pub fn remove_annotation(&mut self, tag: Tag) -> Result<(), Error> {
+        let op = {
+            let styles = self.styles()?;
+
+            styles.rm(tag, styles.get(&tag).derive_rm_ctx())
+        };
+
+        self.apply(Op::Styles(op));
 
-pub type Actor = u8;
+        Ok(())
+    }
 
-pub type Cursor = usize;
+    /// Takes `style` off `range`, cutting any span that reaches past it down
+    /// to the parts outside.
+    pub fn unstyle(
+        &mut self,
+        range: Range<usize>,
+        style: &Style,
+        actor: Actor,
+    ) -> Result<(), Error> {
+        self.check(&range)?;
+
+        let overlapping = self.annotations()?.into_iter().filter(|annotation| {
+            annotation.style == *style
+                && annotation.range.start < range.end
+                && range.start < annotation.range.end
+        });
+
+        for annotation in overlapping.collect::<Vec<_>>() {
+            self.remove_annotation(annotation.tag)?;
+
+            if annotation.range.start < range.start {
+                self.annotate(annotation.range.start..range.start, style.clone(), actor)?;
+            }
 
-#[derive(Clone)]
-pub struct Buffer {
-    buffer: Arc<Mutex<List<char, Actor>>>,
-    cursors: Arc<Mutex<MVReg<Cursor, Actor>>>,
+            if range.end < annotation.range.end {
+                self.annotate(range.end..annotation.range.end, style.clone(), actor)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Takes `style` off every selection it already covers whole, and puts
+    /// it on the others.
+    pub fn toggle_style(&mut self, style: Style, actor: Actor) -> Result<(), Error> {
+        for selection in self.selections(actor)? {
+            if selection.is_empty() {
+                continue;
+            }
+
+            let range = selection.range();
+            let annotations = self.annotations()?;
+
+            let mut covered = range.start;
+
+            for annotation in annotations.iter().filter(|a| a.style == style) {
+                if annotation.range.start <= covered && covered < annotation.range.end {
+                    covered = annotation.range.end;
+                }
+            }
+
+            if covered >= range.end {
+                self.unstyle(range, &style, actor)?;
+                continue;
+            }
+
+            // Spans of the style that overlap or touch the selection are
+            // folded into the new one rather than left stacked under it.
+            let (mut start, mut end) = (range.start, range.end);
+
+            for annotation in self.annotations()?.into_iter().filter(|a| a.style == style) {
+                if annotation.range.start <= end && start <= annotation.range.end {
+                    start = start.min(annotation.range.start);
+                    end = end.max(annotation.range.end);
+
+                    self.remove_annotation(annotation.tag)?;
+                }
+            }
+
+            self.annotate(start..end, style.clone(), actor)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl Buffer {
-    pub fn new() -> Self {
+    fn commit(&mut self, op: list::Op<char, Actor>) -> Result<(), Error> {
+        let op = Op::List(op);
+
+        self.validate_op(&op)?;
+        self.apply(op);
+
+        Ok(())
+    }
+
+    /// Hears of every change from here on, whoever makes it. A subscriber
+    /// that falls too far behind is told it lagged, and should read the
+    /// buffer again.
+    pub fn subscribe(&self) -> broadcast::Receiver<Change> {
+        self.changes.subscribe()
+    }
+
+    /// Takes every op applied since the last call, local or remote, in the
+    /// order they were applied.
+    pub fn drain(&self) -> Result<Vec<Op>, Error> {
+        let mut journal = self.journal.lock().map_err(|_| Error::JournalLock)?;
+
+        Ok(journal.drain(..).collect())
+    }
+
+    pub fn snapshot(&self) -> Result<Snapshot, Error> {
+        Ok(Snapshot {
+            elements: self
+                .buffer
+                .lock()
+                .map_err(|_| Error::BufferLock)?
+                .iter_entries()
+                .map(|(id, value)| (id.clone(), *value))
+                .collect(),
+            clock: self.index()?.clock().clone(),
+            cursors: self.cursors.lock().map_err(|_| Error::CursorLock)?.clone(),
+            marks: self.marks.lock().map_err(|_| Error::MarkLock)?.clone(),
+            styles: self.styles()?.clone(),
+        })
+    }
+}
+
+impl From<Snapshot> for Buffer {
+    /// Replays the elements in the order their actors made them, which the
+    /// list's causality check accepts, then brings its clock up to the one
+    /// saved with a delete of an identifier ordered before everything else.
+    fn from(mut snapshot: Snapshot) -> Self {
+        let mut list = List::new();
+
+        snapshot
+            .elements
+            .sort_by(|(a, _), (b, _)| a.value().cmp(b.value()));
+
+        for (id, val) in snapshot.elements {
+            list.apply(list::Op::Insert { id, val });
+        }
+
+        for dot in snapshot.clock.iter() {
+            let dot = Dot::new(*dot.actor, dot.counter);
+            let first = list.first_entry().map(|(id, _)| id.clone());
+            let id = Id::between(None, first.as_ref(), dot.into());
+
+            list.apply(list::Op::Delete { id, dot });
+        }
+
         Self {
-            buffer: Arc::new(Mutex::new(List::new())),
-            cursors: Arc::new(Mutex::new(MVReg::new())),
+            index: Arc::new(Mutex::new(Index::from_list(&list, snapshot.clock))),
+            buffer: Arc::new(Mutex::new(list)),
+            cursors: Arc::new(Mutex::new(snapshot.cursors)),
+            marks: Arc::new(Mutex::new(snapshot.marks)),
+            styles: Arc::new(Mutex::new(snapshot.styles)),
+            ..Self::new()
+        }
+    }
+}
+
+impl CmRDT for Buffer {
+    type Op = Op;
+    type Validation = Error;
+
+    fn validate_op(&self, op: &Self::Op) -> Result<(), Error> {
+        match op {
+            Op::List(op) => {
+                let buffer = self.buffer.lock().map_err(|_| Error::BufferLock)?;
+
+                buffer.validate_op(op).map_err(Error::from)
+            }
+            Op::Cursors(op) => {
+                let cursors = self.cursors.lock().map_err(|_| Error::CursorLock)?;
+
+                cursors
+                    .validate_op(op)
+                    .map_err(|error| Error::Invalid(error.to_string()))
+            }
+            Op::Marks(op) => {
+                let marks = self.marks.lock().map_err(|_| Error::MarkLock)?;
+
+                marks
+                    .validate_op(op)
+                    .map_err(|error| Error::Invalid(error.to_string()))
+            }
+            Op::Styles(op) => self
+                .styles()?
+                .validate_op(op)
+                .map_err(|error| Error::Invalid(error.to_string())),
+        }
+    }
+
+    fn apply(&mut self, op: Self::Op) {
+        let change = match &op {
+            Op::List(op) => match (self.buffer.lock(), self.index.lock()) {
+                (Ok(mut buffer), Ok(mut index)) => {
+                    buffer.apply(op.clone());
+                    index.observe(op, &buffer)
+                }
+                _ => None,
+            },
+            Op::Cursors(op) => self.cursors.lock().ok().map(|mut cursors| {
+                cursors.apply(op.clone());
+                Change::Selected
+            }),
+            Op::Marks(op) => self.marks.lock().ok().map(|mut marks| {
+                marks.apply(op.clone());
+                Change::Marked
+            }),
+            Op::Styles(op) => self.styles.lock().ok().map(|mut styles| {
+                styles.apply(op.clone());
+                Change::Styled
+            }),
+        };
+
+        if let Some(Change::Edited { .. }) = change {
+            self.revision.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if let Ok(mut journal) = self.journal.lock() {
+            journal.push(op);
+        }
+
+        if let Some(change) = change {
+            notify(&self.changes, change);
+        }
+    }
+}
+
+impl Display for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let buffer = self.buffer.lock().map_err(|_| fmt::Error)?;
+
+        for c in buffer.iter() {
+            write!(f, "{}", c)?;
         }
+
+        Ok(())
+    }
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -40,23 +1520,158 @@ impl Buffer {
     fn display(&self, item: impl Display) -> Result<(), Error> {
         let item = item.to_string();
 
-        let cursors = self.cursors.lock().map_err(|_| Error::CursorLock)?;
+        let mut cursors = vec![];
+
+        for actor in self.actors()? {
+            cursors.extend(
+                self.selections(actor)?
+                    .into_iter()
+                    .map(|selection| selection.head),
+            );
+        }
 
-        let read = cursors.read();
+        if cursors.is_empty() {
+            cursors.push(self.len()?);
+        }
 
-        let mut buffer = self.buffer.lock().map_err(|_| Error::BufferLock)?;
+        cursors.sort_unstable();
+        cursors.dedup();
 
-        for cursor in read.val {
-            for value in item.chars() {
-                let op = buffer.insert_index(cursor, value, 0);
-                buffer.apply(op);
-            }
+        let mut buffer = self.clone();
+
+        for cursor in cursors.into_iter().rev() {
+            buffer.insert(cursor, &item, 0)?;
         }
 
         Ok(())
     }
 }
 
+/// Where the next value lands relative to the text already written: at the
+/// start of a fresh line, after a `key:` or after a sequence item's `-`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Placement {
+    Line,
+    Key,
+    Item,
+}
+
+#[derive(Clone, Copy)]
+struct Layout {
+    depth: usize,
+    position: Placement,
+    entries: usize,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            depth: 0,
+            position: Placement::Line,
+            entries: 0,
+        }
+    }
+}
+
+fn indent(depth: usize) -> String {
+    "  ".repeat(depth)
+}
+
+/// Strings that could be read back as something else are quoted.
+fn plain(text: &str) -> bool {
+    let reserved = ["None", "()", "[]", "{}", "true", "false", "-"];
+
+    !text.is_empty()
+        && text.trim() == text
+        && !text.chars().any(char::is_control)
+        && !text.starts_with(['"', '#'])
+        && !text.starts_with("- ")
+        && !text.contains(": ")
+        && !text.ends_with(':')
+        && !reserved.contains(&text)
+        && text.parse::<f64>().is_err()
+}
+
+impl Buffer {
+    fn at(&self, depth: usize, position: Placement) -> Buffer {
+        Buffer {
+            layout: Layout {
+                depth,
+                position,
+                entries: 0,
+            },
+            ..self.clone()
+        }
+    }
+
+    fn scalar(&self, value: impl Display) -> Result<(), Error> {
+        match self.layout.position {
+            Placement::Line => self.display(format!("{}{}\n", indent(self.layout.depth), value)),
+            Placement::Key | Placement::Item => self.display(format!(" {}\n", value)),
+        }
+    }
+
+    fn text(&self, value: &str) -> Result<(), Error> {
+        if plain(value) {
+            self.scalar(value)
+        } else {
+            self.scalar(format!("{:?}", value))
+        }
+    }
+
+    fn compound(&self) -> Buffer {
+        let depth = match self.layout.position {
+            Placement::Line => self.layout.depth,
+            Placement::Key | Placement::Item => self.layout.depth + 1,
+        };
+
+        self.at(depth, self.layout.position)
+    }
+
+    fn entry(&mut self, label: impl Display) -> Result<(), Error> {
+        let prefix = match (self.layout.entries, self.layout.position) {
+            (0, Placement::Key) => format!("\n{}", indent(self.layout.depth)),
+            (0, Placement::Item) => " ".into(),
+            _ => indent(self.layout.depth),
+        };
+
+        self.layout.entries += 1;
+
+        self.display(format!("{}{}", prefix, label))
+    }
+
+    fn item<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.entry("-")?;
+
+        value.serialize(self.at(self.layout.depth, Placement::Item))
+    }
+
+    fn field<T: ?Sized + Serialize>(&mut self, key: impl Display, value: &T) -> Result<(), Error> {
+        self.entry(format_args!("{}:", key))?;
+
+        value.serialize(self.at(self.layout.depth, Placement::Key))
+    }
+
+    fn close(&self, empty: &str) -> Result<(), Error> {
+        if self.layout.entries > 0 {
+            return Ok(());
+        }
+
+        match self.layout.position {
+            Placement::Line => self.display(format!("{}{}\n", indent(self.layout.depth), empty)),
+            Placement::Key | Placement::Item => self.display(format!(" {}\n", empty)),
+        }
+    }
+
+    fn variant(&self, variant: &'static str) -> Result<Buffer, Error> {
+        let mut outer = self.compound();
+
+        outer.entry(format_args!("{}:", variant))?;
+
+        Ok(outer.at(outer.layout.depth, Placement::Key).compound())
+    }
+}
+
 impl serde::ser::Error for Error {
     fn custom<T: std::fmt::Display>(msg: T) -> Error {
         Error::Serialize(msg.to_string())
@@ -76,159 +1691,159 @@ impl Serializer for Buffer {
     type SerializeTupleVariant = Buffer;
 
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
-        self.display(v)
+        self.scalar(v)
     }
 
     fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
-        self.display(v)
+        self.scalar(v)
     }
 
     fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
-        self.display(v)
+        self.scalar(v)
     }
 
     fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
-        self.display(v)
+        self.scalar(v)
     }
 
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
-        self.display(v)
+        self.scalar(v)
     }
 
     fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
-        self.display(v)
+        self.scalar(v)
     }
 
     fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
-        self.display(v)
+        self.scalar(v)
     }
 
     fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
-        self.display(v)
+        self.scalar(v)
     }
 
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
-        self.display(v)
+        self.scalar(v)
     }
 
     fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
-        self.display(v)
+        self.scalar(v)
     }
 
     fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
-        self.display(v)
+        self.scalar(v)
     }
 
     fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
-        self.display(v)
+        self.text(v.encode_utf8(&mut [0; 4]))
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
-        v.serialize(self.clone())
+        self.text(v)
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        unimplemented!()
+        self.collect_seq(v)
     }
 
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
-        unimplemented!()
+        self.scalar("None")
     }
 
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        unimplemented!()
+        value.serialize(self)
     }
 
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
-        unimplemented!()
+        self.scalar("()")
     }
 
     fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok, Self::Error> {
-        unimplemented!()
+        self.scalar(name)
     }
 
     fn serialize_unit_variant(
         self,
-        name: &'static str,
-        variant_index: u32,
+        _: &'static str,
+        _: u32,
         variant: &'static str,
     ) -> Result<Self::Ok, Self::Error> {
-        unimplemented!()
+        self.scalar(variant)
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T>(
         self,
-        name: &'static str,
+        _: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        unimplemented!()
+        value.serialize(self)
     }
 
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
-        name: &'static str,
-        variant_index: u32,
+        _: &'static str,
+        _: u32,
         variant: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        unimplemented!()
+        self.compound().field(variant, value)
     }
 
-    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
-        Ok(self.clone())
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(self.compound())
     }
 
-    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
-        Ok(self.clone())
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Ok(self.compound())
     }
 
     fn serialize_tuple_struct(
         self,
-        name: &'static str,
-        len: usize,
+        _: &'static str,
+        _: usize,
     ) -> Result<Self::SerializeTupleStruct, Self::Error> {
-        Ok(self.clone())
+        Ok(self.compound())
     }
 
     fn serialize_tuple_variant(
         self,
-        name: &'static str,
-        variant_index: u32,
+        _: &'static str,
+        _: u32,
         variant: &'static str,
-        len: usize,
+        _: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
-        Ok(self.clone())
+        self.variant(variant)
     }
 
-    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        Ok(self.clone())
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(self.compound())
     }
 
     fn serialize_struct(
         self,
-        name: &'static str,
-        len: usize,
+        _: &'static str,
+        _: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        Ok(self.clone())
+        Ok(self.compound())
     }
 
     fn serialize_struct_variant(
         self,
-        name: &'static str,
-        variant_index: u32,
+        _: &'static str,
+        _: u32,
         variant: &'static str,
-        len: usize,
+        _: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        Ok(self.clone())
+        self.variant(variant)
     }
 }
 
@@ -236,17 +1851,15 @@ impl ser::SerializeSeq for Buffer {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
     where
-        T: ser::Serialize,
+        T: ?Sized + Serialize,
     {
-        value.serialize(self.clone())?;
-
-        self.display("\n")
+        self.item(value)
     }
 
     fn end(self) -> Result<(), Error> {
-        Ok(())
+        self.close("[]")
     }
 }
 
@@ -254,22 +1867,33 @@ impl ser::SerializeMap for Buffer {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), Self::Error>
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        unimplemented!()
+        let scratch = Buffer::new();
+
+        key.serialize(scratch.clone())?;
+
+        let key = scratch.to_string();
+        let key = key.strip_suffix('\n').unwrap_or(&key);
+
+        if key.contains('\n') {
+            return Err(Error::Key(key.into()));
+        }
+
+        self.entry(format_args!("{}:", key))
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        unimplemented!()
+        value.serialize(self.at(self.layout.depth, Placement::Key))
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        unimplemented!()
+        self.close("{}")
     }
 }
 
@@ -277,15 +1901,15 @@ impl ser::SerializeTuple for Buffer {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        unimplemented!()
+        self.item(value)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        unimplemented!()
+        self.close("[]")
     }
 }
 
@@ -293,19 +1917,15 @@ impl ser::SerializeStruct for Buffer {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        unimplemented!()
+        self.field(key, value)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        unimplemented!()
+        self.close("{}")
     }
 }
 
@@ -313,19 +1933,15 @@ impl ser::SerializeStructVariant for Buffer {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        unimplemented!()
+        self.field(key, value)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        unimplemented!()
+        self.close("{}")
     }
 }
 
@@ -333,15 +1949,15 @@ impl ser::SerializeTupleStruct for Buffer {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        unimplemented!()
+        self.item(value)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        unimplemented!()
+        self.close("[]")
     }
 }
 
@@ -349,14 +1965,395 @@ impl ser::SerializeTupleVariant for Buffer {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        unimplemented!()
+        self.item(value)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        unimplemented!()
+        self.close("[]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        fixtures::{buffer, replicate},
+    };
+
+    #[test]
+    fn marks_keep_to_their_text() {
+        let mut buffer = buffer("one two three");
+
+        buffer.set_mark("two", 4, 0).unwrap();
+        buffer.insert(0, "zero ", 1).unwrap();
+        buffer.insert(buffer.len().unwrap(), " four", 1).unwrap();
+
+        assert_eq!(buffer.mark("two").unwrap(), Some(9));
+
+        buffer.delete(0..5, 1).unwrap();
+        buffer.jump("two", 0).unwrap();
+
+        assert_eq!(buffer.cursor(0).unwrap(), 4);
+        assert_eq!(buffer.marks().unwrap(), vec![("two".into(), 4)]);
+
+        buffer.remove_mark("two").unwrap();
+
+        assert_eq!(buffer.mark("two").unwrap(), None);
+        assert!(buffer.jump("two", 0).is_err());
+    }
+
+    #[test]
+    fn marks_settle_where_deleted_text_was() {
+        let mut buffer = buffer("one two three");
+
+        buffer.set_mark("two", 6, 0).unwrap();
+        buffer.delete(3..8, 1).unwrap();
+
+        assert_eq!(buffer.to_string(), "onethree");
+        assert_eq!(buffer.mark("two").unwrap(), Some(3));
+    }
+
+    #[test]
+    fn selections_keep_to_their_text() {
+        let mut buffer = buffer("one two three");
+
+        buffer.select(4..7, 0).unwrap();
+        buffer.insert(0, ">> ", 1).unwrap();
+        buffer.insert(10, "!", 1).unwrap();
+
+        assert_eq!(buffer.selection(0).unwrap(), Selection::from(7..10));
+        assert_eq!(buffer.selection(1).unwrap(), Selection::caret(0));
+    }
+
+    #[test]
+    fn marks_and_selections_replicate() {
+        let mut local = buffer("alpha beta");
+        let mut remote = Buffer::new();
+
+        local.set_mark("beta", 6, 0).unwrap();
+        local.select(1..5, 0).unwrap();
+
+        replicate(&local, &mut remote);
+
+        remote.insert(0, "- ", 1).unwrap();
+
+        replicate(&remote, &mut local);
+
+        for buffer in [&local, &remote] {
+            assert_eq!(buffer.to_string(), "- alpha beta");
+            assert_eq!(buffer.mark("beta").unwrap(), Some(8));
+            assert_eq!(buffer.selection(0).unwrap(), Selection::from(3..7));
+        }
+    }
+
+    #[test]
+    fn typing_at_every_cursor_shifts_the_ones_after() {
+        let mut buffer = buffer("a b c");
+
+        buffer
+            .set_selections(
+                vec![
+                    Selection::caret(1),
+                    Selection::caret(3),
+                    Selection::caret(5),
+                ],
+                0,
+            )
+            .unwrap();
+        buffer.write("xy", 0).unwrap();
+
+        assert_eq!(buffer.to_string(), "axy bxy cxy");
+        assert_eq!(
+            buffer.selections(0).unwrap(),
+            vec![
+                Selection::caret(3),
+                Selection::caret(7),
+                Selection::caret(11)
+            ]
+        );
+
+        buffer.backspace(0).unwrap();
+
+        assert_eq!(buffer.to_string(), "ax bx cx");
+        assert_eq!(
+            buffer.selections(0).unwrap(),
+            vec![
+                Selection::caret(2),
+                Selection::caret(5),
+                Selection::caret(8)
+            ]
+        );
+    }
+
+    #[test]
+    fn typing_replaces_every_selection() {
+        let mut buffer = buffer("one two one");
+
+        buffer
+            .set_selections(vec![Selection::from(0..3), Selection::from(8..11)], 0)
+            .unwrap();
+        buffer.write("1", 0).unwrap();
+
+        assert_eq!(buffer.to_string(), "1 two 1");
+
+        buffer.delete_forward(0).unwrap();
+
+        assert_eq!(buffer.to_string(), "1two 1");
+    }
+
+    #[test]
+    fn overlapping_selections_merge() {
+        let mut buffer = buffer("abcdef");
+
+        buffer
+            .set_selections(vec![Selection::from(0..3), Selection::from(2..5)], 0)
+            .unwrap();
+
+        assert_eq!(buffer.selections(0).unwrap(), vec![Selection::from(0..5)]);
+    }
+
+    #[test]
+    fn pasting_a_line_per_selection_distributes_them() {
+        let mut buffer = buffer("a\nb\nc");
+
+        buffer
+            .set_selections(
+                vec![
+                    Selection::caret(1),
+                    Selection::caret(3),
+                    Selection::caret(5),
+                ],
+                0,
+            )
+            .unwrap();
+        buffer.paste("1\n2\n3", 0).unwrap();
+
+        assert_eq!(buffer.to_string(), "a1\nb2\nc3");
+
+        buffer.paste("!", 0).unwrap();
+
+        assert_eq!(buffer.to_string(), "a1!\nb2!\nc3!");
+    }
+
+    #[test]
+    fn select_next_adds_the_next_occurrence() {
+        let mut buffer = buffer("foo bar foo baz foo");
+
+        buffer.set_cursor(1, 0).unwrap();
+        buffer.select_next(0).unwrap();
+
+        assert_eq!(buffer.selections(0).unwrap(), vec![Selection::from(0..3)]);
+
+        buffer.select_next(0).unwrap();
+        buffer.select_next(0).unwrap();
+        buffer.select_next(0).unwrap();
+
+        assert_eq!(
+            buffer.selections(0).unwrap(),
+            vec![
+                Selection::from(0..3),
+                Selection::from(8..11),
+                Selection::from(16..19)
+            ]
+        );
+
+        buffer.write("qux", 0).unwrap();
+
+        assert_eq!(buffer.to_string(), "qux bar qux baz qux");
+    }
+
+    #[test]
+    fn split_lines_gives_a_selection_per_line() {
+        let mut buffer = buffer("ab\ncd\nef");
+
+        buffer.select(1..7, 0).unwrap();
+        buffer.split_lines(0).unwrap();
+
+        assert_eq!(
+            buffer.selections(0).unwrap(),
+            vec![
+                Selection::from(1..2),
+                Selection::from(3..5),
+                Selection::from(6..7)
+            ]
+        );
+
+        buffer.write("-", 0).unwrap();
+
+        assert_eq!(buffer.to_string(), "a-\n-\n-f");
+    }
+
+    #[test]
+    fn find_next_wraps_around() {
+        let mut buffer = buffer("one two one two");
+        let query = Query::literal("two");
+
+        buffer.set_cursor(8, 0).unwrap();
+
+        assert!(buffer.find_next(&query, Direction::Forward, 0).unwrap());
+        assert_eq!(buffer.selection(0).unwrap().range(), 12..15);
+
+        assert!(buffer.find_next(&query, Direction::Forward, 0).unwrap());
+        assert_eq!(buffer.selection(0).unwrap().range(), 4..7);
+
+        assert!(buffer.find_next(&query, Direction::Backward, 0).unwrap());
+        assert_eq!(buffer.selection(0).unwrap().range(), 12..15);
+
+        assert!(!buffer
+            .find_next(&Query::literal("three"), Direction::Forward, 0)
+            .unwrap());
+    }
+
+    #[test]
+    fn replace_next_only_replaces_a_selected_match() {
+        let mut buffer = buffer("one two one two");
+        let query = Query::literal("one");
+
+        buffer.set_cursor(0, 0).unwrap();
+
+        // Nothing is selected yet, so this only finds the first match.
+        assert!(!buffer.replace_next(&query, "1", 0).unwrap());
+        assert_eq!(buffer.to_string(), "one two one two");
+        assert_eq!(buffer.selection(0).unwrap().range(), 0..3);
+
+        assert!(buffer.replace_next(&query, "1", 0).unwrap());
+        assert_eq!(buffer.to_string(), "1 two one two");
+        assert_eq!(buffer.selection(0).unwrap().range(), 6..9);
+
+        assert!(buffer.replace_next(&query, "1", 0).unwrap());
+        assert_eq!(buffer.to_string(), "1 two 1 two");
+    }
+
+    #[test]
+    fn replace_all_is_one_undo_step() {
+        let mut buffer = buffer("a=1, b=2, c=3");
+        let query = Query::regex(r"(\w)=(\d)");
+
+        assert_eq!(buffer.replace_all(&query, "$2:$1", 0).unwrap(), 3);
+        assert_eq!(buffer.to_string(), "1:a, 2:b, 3:c");
+
+        assert_eq!(buffer.replace_all(&Query::literal("z"), "y", 0).unwrap(), 0);
+
+        assert!(buffer.undo(0).unwrap());
+        assert_eq!(buffer.to_string(), "a=1, b=2, c=3");
+    }
+
+    #[test]
+    fn bad_regexes_are_errors() {
+        let mut buffer = buffer("text");
+
+        assert!(buffer.search(&Query::regex("(")).is_err());
+        assert!(buffer.replace_all(&Query::regex("("), "", 0).is_err());
+        assert_eq!(buffer.search(&Query::literal("(")).unwrap(), vec![]);
+    }
+
+    fn styled(buffer: &Buffer) -> Vec<(Style, Range<usize>)> {
+        buffer
+            .annotations()
+            .unwrap()
+            .into_iter()
+            .map(|annotation| (annotation.style, annotation.range))
+            .collect()
+    }
+
+    #[test]
+    fn bold_takes_in_text_typed_at_its_end_but_links_do_not() {
+        let mut buffer = buffer("bold link");
+        let link = Style::Link("https://example.com".into());
+
+        buffer.annotate(0..4, Style::Bold, 0).unwrap();
+        buffer.annotate(5..9, link.clone(), 0).unwrap();
+
+        buffer.insert(9, "s", 0).unwrap();
+        buffer.insert(4, "er", 0).unwrap();
+        buffer.insert(0, ">", 0).unwrap();
+
+        assert_eq!(buffer.to_string(), ">bolder links");
+        assert_eq!(
+            styled(&buffer),
+            vec![(Style::Bold, 1..7), (link.clone(), 8..12)]
+        );
+        assert_eq!(buffer.styles_at(3).unwrap(), vec![Style::Bold]);
+        assert_eq!(buffer.styles_at(12).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn spans_go_once_their_text_is_deleted() {
+        let mut buffer = buffer("one two three");
+
+        let tag = buffer.annotate(4..7, Style::Italic, 0).unwrap();
+
+        buffer.annotate(8..13, Style::Italic, 0).unwrap();
+        buffer.delete(3..7, 0).unwrap();
+
+        assert_eq!(styled(&buffer), vec![(Style::Italic, 4..9)]);
+
+        // Removing a span that is already gone changes nothing.
+        buffer.remove_annotation(tag).unwrap();
+
+        assert_eq!(styled(&buffer), vec![(Style::Italic, 4..9)]);
+        assert!(buffer.annotate(0..20, Style::Bold, 0).is_err());
+    }
+
+    #[test]
+    fn unstyling_the_middle_of_a_span_splits_it() {
+        let mut buffer = buffer("one two three");
+
+        buffer.annotate(0..13, Style::Bold, 0).unwrap();
+        buffer.annotate(0..13, Style::Underline, 0).unwrap();
+        buffer.unstyle(4..7, &Style::Bold, 0).unwrap();
+
+        assert_eq!(
+            styled(&buffer),
+            vec![
+                (Style::Underline, 0..13),
+                (Style::Bold, 0..4),
+                (Style::Bold, 7..13),
+            ]
+        );
+    }
+
+    #[test]
+    fn toggling_styles_covers_or_uncovers_each_selection() {
+        let mut buffer = buffer("one two three");
+
+        buffer.annotate(0..3, Style::Bold, 0).unwrap();
+        buffer.select(2..7, 0).unwrap();
+        buffer.toggle_style(Style::Bold, 0).unwrap();
+
+        assert_eq!(styled(&buffer), vec![(Style::Bold, 0..7)]);
+
+        buffer.select(4..7, 0).unwrap();
+        buffer.toggle_style(Style::Bold, 0).unwrap();
+
+        assert_eq!(styled(&buffer), vec![(Style::Bold, 0..4)]);
+    }
+
+    #[test]
+    fn styles_replicate() {
+        let mut local = buffer("one two");
+        let mut remote = Buffer::new();
+
+        replicate(&local, &mut remote);
+
+        let tag = local.annotate(4..7, Style::Highlight, 0).unwrap();
+
+        replicate(&local, &mut remote);
+
+        assert_eq!(styled(&remote), vec![(Style::Highlight, 4..7)]);
+
+        remote.insert(7, "!", 1).unwrap();
+        replicate(&remote, &mut local);
+
+        assert_eq!(styled(&local), vec![(Style::Highlight, 4..8)]);
+
+        local.remove_annotation(tag).unwrap();
+        replicate(&local, &mut remote);
+
+        assert_eq!(styled(&remote), vec![]);
     }
 }
@@ -0,0 +1,725 @@
+use super::Error;
+
+use ::{
+    serde::de::{
+        self, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer,
+        MapAccess, SeqAccess, VariantAccess, Visitor,
+    },
+    std::{borrow::Cow, slice},
+};
+
+impl de::Error for Error {
+    fn custom<T: std::fmt::Display>(msg: T) -> Error {
+        Error::Deserialize {
+            line: 0,
+            column: 0,
+            message: msg.to_string(),
+        }
+    }
+}
+
+impl Error {
+    fn at(line: usize, column: usize, message: impl Into<String>) -> Error {
+        Error::Deserialize {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn locate(self, node: &Node) -> Error {
+        match self {
+            Error::Deserialize {
+                line: 0, message, ..
+            } => Error::at(node.line, node.column, message),
+            error => error,
+        }
+    }
+}
+
+pub fn from_str<T: DeserializeOwned>(text: &str) -> Result<T, Error> {
+    let node = parse(text)?;
+
+    T::deserialize(&node).map_err(|error: Error| error.locate(&node))
+}
+
+#[derive(Debug)]
+pub(crate) struct Node {
+    pub line: usize,
+    pub column: usize,
+    pub value: Value,
+}
+
+#[derive(Debug)]
+pub(crate) enum Value {
+    Scalar(String),
+    Sequence(Vec<Node>),
+    Map(Vec<(Node, Node)>),
+}
+
+/// One line of the layout, with each leading `- ` split off into its own
+/// item token so nested items read like ordinary indented blocks.
+struct Token<'a> {
+    line: usize,
+    column: usize,
+    item: bool,
+    text: &'a str,
+}
+
+fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut rest = line.trim_end();
+        let mut column = 1;
+
+        loop {
+            let trimmed = rest.trim_start_matches(' ');
+
+            column += rest.len() - trimmed.len();
+            rest = trimmed;
+
+            let item = rest == "-" || rest.starts_with("- ");
+
+            tokens.push(Token {
+                line: index + 1,
+                column,
+                item,
+                text: if item { "-" } else { rest },
+            });
+
+            if !item || rest == "-" {
+                break;
+            }
+
+            rest = &rest[1..];
+            column += 1;
+        }
+    }
+
+    tokens
+}
+
+pub(crate) fn parse(text: &str) -> Result<Node, Error> {
+    let mut parser = Parser {
+        tokens: tokenize(text),
+        index: 0,
+    };
+
+    let column = match parser.tokens.first() {
+        Some(token) => token.column,
+        None => return Err(Error::at(1, 1, "expected a value")),
+    };
+
+    let node = parser.node(column)?;
+
+    match parser.peek() {
+        Some(token) => Err(Error::at(token.line, token.column, "unexpected content")),
+        None => Ok(node),
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    index: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.index)
+    }
+
+    /// Peeks at the next token belonging to the block at `column`, failing
+    /// on anything indented deeper than the block itself.
+    fn member(&self, column: usize) -> Result<Option<&Token<'a>>, Error> {
+        match self.peek() {
+            Some(token) if token.column > column => Err(Error::at(
+                token.line,
+                token.column,
+                "unexpected indentation",
+            )),
+            Some(token) if token.column == column => Ok(Some(token)),
+            _ => Ok(None),
+        }
+    }
+
+    fn node(&mut self, column: usize) -> Result<Node, Error> {
+        let (line, item, text) = match self.peek() {
+            Some(token) => (token.line, token.item, token.text),
+            None => return Err(Error::at(0, 0, "expected a value")),
+        };
+
+        if item {
+            self.sequence(line, column)
+        } else if entry(text).is_some() {
+            self.map(line, column)
+        } else {
+            self.index += 1;
+
+            Ok(Node {
+                line,
+                column,
+                value: Value::Scalar(text.into()),
+            })
+        }
+    }
+
+    fn child(&mut self, line: usize, column: usize) -> Result<Node, Error> {
+        match self.peek() {
+            Some(token) if token.column > column => {
+                let column = token.column;
+
+                self.node(column)
+            }
+            _ => Err(Error::at(line, column, "expected a value")),
+        }
+    }
+
+    fn sequence(&mut self, line: usize, column: usize) -> Result<Node, Error> {
+        let mut items = vec![];
+
+        while let Some(token) = self.member(column)? {
+            let (line, column, item) = (token.line, token.column, token.item);
+
+            if !item {
+                return Err(Error::at(line, column, "expected `-`"));
+            }
+
+            self.index += 1;
+
+            items.push(self.child(line, column)?);
+        }
+
+        Ok(Node {
+            line,
+            column,
+            value: Value::Sequence(items),
+        })
+    }
+
+    fn map(&mut self, line: usize, column: usize) -> Result<Node, Error> {
+        let mut entries = vec![];
+
+        while let Some(token) = self.member(column)? {
+            let (line, column, item, text) = (token.line, token.column, token.item, token.text);
+
+            let (key, value) = match entry(text) {
+                Some(entry) if !item => entry,
+                _ => return Err(Error::at(line, column, "expected `key:`")),
+            };
+
+            self.index += 1;
+
+            let key = Node {
+                line,
+                column,
+                value: Value::Scalar(key.into()),
+            };
+
+            let value = match value {
+                Some((offset, value)) => Node {
+                    line,
+                    column: column + offset,
+                    value: Value::Scalar(value.into()),
+                },
+                None => self.child(line, column)?,
+            };
+
+            entries.push((key, value));
+        }
+
+        Ok(Node {
+            line,
+            column,
+            value: Value::Map(entries),
+        })
+    }
+}
+
+/// Splits `key: value` or `key:` into the raw key and the inline value along
+/// with its character offset from the start of the line.
+fn entry(text: &str) -> Option<(&str, Option<(usize, &str)>)> {
+    let split = if text.starts_with('"') {
+        let end = quoted(text)?;
+
+        match &text[end..] {
+            ":" => end,
+            rest if rest.starts_with(": ") => end,
+            _ => return None,
+        }
+    } else if let Some(index) = text.find(": ") {
+        index
+    } else if text.ends_with(':') {
+        text.len() - 1
+    } else {
+        return None;
+    };
+
+    let key = &text[..split];
+    let rest = &text[split + 1..];
+    let value = rest.trim_start();
+
+    if value.is_empty() {
+        return Some((key, None));
+    }
+
+    let offset = text[..text.len() - value.len()].chars().count();
+
+    Some((key, Some((offset, value))))
+}
+
+/// Byte index just past the closing quote of the string starting `text`.
+fn quoted(text: &str) -> Option<usize> {
+    let mut escaped = false;
+
+    for (index, c) in text.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some(index + 1),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn unquote(raw: &str) -> Result<String, String> {
+    if quoted(raw) != Some(raw.len()) {
+        return Err(format!("unterminated string {}", raw));
+    }
+
+    let mut text = String::new();
+    let mut chars = raw[1..raw.len() - 1].chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => text.push('\n'),
+            Some('r') => text.push('\r'),
+            Some('t') => text.push('\t'),
+            Some('0') => text.push('\0'),
+            Some(c @ ('\\' | '"' | '\'')) => text.push(c),
+            Some('u') => {
+                let code = chars
+                    .by_ref()
+                    .skip_while(|c| *c == '{')
+                    .take_while(|c| *c != '}')
+                    .collect::<String>();
+
+                let c = u32::from_str_radix(&code, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("invalid escape \\u{{{}}}", code))?;
+
+                text.push(c);
+            }
+            other => return Err(format!("invalid escape \\{}", other.unwrap_or(' '))),
+        }
+    }
+
+    Ok(text)
+}
+
+impl Node {
+    fn text(&self) -> Result<Cow<'_, str>, Error> {
+        match &self.value {
+            Value::Scalar(raw) if raw.starts_with('"') => unquote(raw)
+                .map(Cow::Owned)
+                .map_err(|message| Error::at(self.line, self.column, message)),
+            Value::Scalar(raw) => Ok(Cow::Borrowed(raw)),
+            _ => Err(Error::at(self.line, self.column, "expected a scalar")),
+        }
+    }
+
+    fn raw(&self) -> Option<&str> {
+        match &self.value {
+            Value::Scalar(raw) => Some(raw),
+            _ => None,
+        }
+    }
+
+    fn visit<'de, V: Visitor<'de>>(&self, visitor: V) -> Result<V::Value, Error> {
+        let raw = match &self.value {
+            Value::Sequence(items) => return visitor.visit_seq(Items(items.iter())),
+            Value::Map(entries) => return visitor.visit_map(Entries::from(entries.as_slice())),
+            Value::Scalar(raw) => raw.as_str(),
+        };
+
+        match raw {
+            _ if raw.starts_with('"') => visitor.visit_string(self.text()?.into_owned()),
+            "None" => visitor.visit_none(),
+            "()" => visitor.visit_unit(),
+            "true" => visitor.visit_bool(true),
+            "false" => visitor.visit_bool(false),
+            "[]" => visitor.visit_seq(Items([].iter())),
+            "{}" => visitor.visit_map(Entries::from(&[][..])),
+            _ => {
+                if let Ok(value) = raw.parse::<i64>() {
+                    visitor.visit_i64(value)
+                } else if let Ok(value) = raw.parse::<u64>() {
+                    visitor.visit_u64(value)
+                } else if let Ok(value) = raw.parse::<f64>() {
+                    visitor.visit_f64(value)
+                } else {
+                    visitor.visit_str(raw)
+                }
+            }
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for &Node {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.visit(visitor)
+            .map_err(|error: Error| error.locate(self))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let text = self.text()?;
+
+        let mut chars = text.chars();
+
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => visitor.visit_str(&text),
+        }
+        .map_err(|error: Error| error.locate(self))
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor
+            .visit_str(&self.text()?)
+            .map_err(|error: Error| error.locate(self))
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.raw() {
+            Some("None") => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+        .map_err(|error: Error| error.locate(self))
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.text()?;
+
+        visitor
+            .visit_unit()
+            .map_err(|error: Error| error.locate(self))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor
+            .visit_newtype_struct(self)
+            .map_err(|error: Error| error.locate(self))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let variant = match &self.value {
+            Value::Scalar(_) => Variant {
+                key: self,
+                value: None,
+            },
+            Value::Map(entries) if entries.len() == 1 => Variant {
+                key: &entries[0].0,
+                value: Some(&entries[0].1),
+            },
+            _ => {
+                return Err(Error::at(
+                    self.line,
+                    self.column,
+                    "expected a variant name or a single `Variant:` entry",
+                ))
+            }
+        };
+
+        visitor
+            .visit_enum(variant)
+            .map_err(|error: Error| error.locate(self))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 bytes byte_buf unit
+        seq tuple tuple_struct map struct ignored_any
+    }
+}
+
+struct Items<'a>(slice::Iter<'a, Node>);
+
+impl<'de, 'a> SeqAccess<'de> for Items<'a> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.0.next() {
+            Some(node) => seed
+                .deserialize(node)
+                .map(Some)
+                .map_err(|error: Error| error.locate(node)),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+struct Entries<'a> {
+    entries: slice::Iter<'a, (Node, Node)>,
+    value: Option<&'a Node>,
+}
+
+impl<'a> From<&'a [(Node, Node)]> for Entries<'a> {
+    fn from(entries: &'a [(Node, Node)]) -> Self {
+        Self {
+            entries: entries.iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for Entries<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+
+                seed.deserialize(key)
+                    .map(Some)
+                    .map_err(|error: Error| error.locate(key))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.value.take() {
+            Some(value) => seed
+                .deserialize(value)
+                .map_err(|error: Error| error.locate(value)),
+            None => Err(de::Error::custom("value requested before key")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct Variant<'a> {
+    key: &'a Node,
+    value: Option<&'a Node>,
+}
+
+impl<'de, 'a> EnumAccess<'de> for Variant<'a> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let name = self.key.text()?;
+
+        let variant = seed
+            .deserialize(name.as_ref().into_deserializer())
+            .map_err(|error: Error| error.locate(self.key))?;
+
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a> VariantAccess<'de> for Variant<'a> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.value {
+            None => Ok(()),
+            Some(value) => Err(Error::at(
+                value.line,
+                value.column,
+                "expected a unit variant",
+            )),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        match self.value {
+            Some(value) => seed
+                .deserialize(value)
+                .map_err(|error: Error| error.locate(value)),
+            None => Err(Error::at(
+                self.key.line,
+                self.key.column,
+                "expected a newtype variant",
+            )),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Some(value) => value.deserialize_seq(visitor),
+            None => Err(Error::at(
+                self.key.line,
+                self.key.column,
+                "expected a tuple variant",
+            )),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.value {
+            Some(value) => value.deserialize_map(visitor),
+            None => Err(Error::at(
+                self.key.line,
+                self.key.column,
+                "expected a struct variant",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::Buffer,
+        serde::{Deserialize, Serialize},
+        std::collections::BTreeMap,
+    };
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Dot,
+        Circle(f64),
+        Line { from: (i64, i64), to: (i64, i64) },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Drawing {
+        name: String,
+        note: Option<String>,
+        tags: Vec<String>,
+        shapes: Vec<Shape>,
+        layers: BTreeMap<String, u8>,
+        mark: char,
+    }
+
+    fn drawing() -> Drawing {
+        Drawing {
+            name: "a: \"quoted\"\n".into(),
+            note: None,
+            tags: vec![],
+            shapes: vec![
+                Shape::Dot,
+                Shape::Circle(1.5),
+                Shape::Line {
+                    from: (0, -1),
+                    to: (2, 3),
+                },
+            ],
+            layers: [("back".into(), 0), ("front".into(), 1)].into(),
+            mark: 'é',
+        }
+    }
+
+    fn error(text: &str) -> (usize, usize, String) {
+        match from_str::<Drawing>(text) {
+            Err(Error::Deserialize {
+                line,
+                column,
+                message,
+            }) => (line, column, message),
+            other => panic!("expected a deserialize error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn what_a_buffer_is_serialized_to_reads_back() {
+        let buffer = Buffer::new();
+
+        drawing().serialize(buffer.clone()).unwrap();
+
+        assert_eq!(from_str::<Drawing>(&buffer.to_string()).unwrap(), drawing());
+    }
+
+    #[test]
+    fn type_errors_point_at_the_value() {
+        let text = "name: x\nnote: None\ntags: []\nshapes:\n  - Dot\n  - Circle: big\nlayers: {}\nmark: x\n";
+
+        let (line, column, message) = error(text);
+
+        assert_eq!((line, column), (6, 13));
+        assert!(message.contains("expected f64"), "{}", message);
+    }
+
+    #[test]
+    fn layout_errors_point_at_the_line_and_column() {
+        assert_eq!(
+            error("name: x\n   note: None\n"),
+            (2, 4, "unexpected indentation".into())
+        );
+        assert_eq!(
+            error("tags:\n  - a\n  b: c\n"),
+            (3, 3, "expected `-`".into())
+        );
+        assert_eq!(error("tags:\n"), (1, 1, "expected a value".into()));
+    }
+
+    #[test]
+    fn bad_strings_point_at_the_string() {
+        let (line, column, message) = error("name: x\nnote: \"open\n");
+
+        assert_eq!((line, column), (2, 7));
+        assert!(message.contains("unterminated"), "{}", message);
+    }
+
+    #[test]
+    fn missing_fields_point_at_the_struct() {
+        let (line, column, message) = error("\n  name: x\n  note: None\n");
+
+        assert_eq!((line, column), (2, 3));
+        assert!(message.contains("missing field `tags`"), "{}", message);
+    }
+}
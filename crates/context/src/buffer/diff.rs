@@ -0,0 +1,435 @@
+use ::std::{
+    iter,
+    ops::{Index, IndexMut, Range},
+};
+
+/// How many edits the search for a middle snake may try before settling for
+/// the furthest point it reached. Keeps wholesale rewrites from costing
+/// quadratic time.
+static LIMIT: usize = 1024;
+
+/// Texts, or the lines that changed between them, are diffed char by char
+/// as long as they are shorter than this together.
+static REFINE: usize = 1 << 16;
+
+/// One change turning the old text into the new: the chars in `delete` go,
+/// and `insert` takes their place. Offsets are chars into the old text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub delete: Range<usize>,
+    pub insert: String,
+}
+
+/// A run of `length` items equal in both sequences, starting at `old` in
+/// one and `new` in the other.
+#[derive(Debug, Clone, Copy)]
+struct Run {
+    old: usize,
+    new: usize,
+    length: usize,
+}
+
+/// Diffs by char, which gives the fewest ops. Large texts are diffed by
+/// line first, which is cheap and matches how files change, and then by
+/// char within the lines that differ.
+pub fn diff(old: &str, new: &str) -> Vec<Hunk> {
+    let old = old.chars().collect::<Vec<_>>();
+    let new = new.chars().collect::<Vec<_>>();
+
+    let mut runs = vec![];
+
+    if old.len() + new.len() <= REFINE {
+        conquer(&old, &new, 0, 0, &mut runs);
+
+        return hunks(&runs, &new, old.len());
+    }
+
+    let (old_lines, old_starts) = lines(&old);
+    let (new_lines, new_starts) = lines(&new);
+
+    let mut line_runs = vec![];
+
+    conquer(&old_lines, &new_lines, 0, 0, &mut line_runs);
+
+    for (old_lines, new_lines) in gaps(&line_runs, old_lines.len(), new_lines.len()) {
+        let old_range = old_starts[old_lines.start]..old_starts[old_lines.end];
+        let new_range = new_starts[new_lines.start]..new_starts[new_lines.end];
+
+        if old_range.len() + new_range.len() <= REFINE {
+            conquer(
+                &old[old_range.clone()],
+                &new[new_range.clone()],
+                old_range.start,
+                new_range.start,
+                &mut runs,
+            );
+        }
+    }
+
+    for run in line_runs {
+        let old = old_starts[run.old];
+        let new = new_starts[run.new];
+
+        runs.push(Run {
+            old,
+            new,
+            length: old_starts[run.old + run.length] - old,
+        });
+    }
+
+    runs.sort_by_key(|run| run.old);
+
+    hunks(&runs, &new, old.len())
+}
+
+fn hunks(runs: &[Run], new: &[char], old: usize) -> Vec<Hunk> {
+    gaps(runs, old, new.len())
+        .map(|(delete, insert)| Hunk {
+            delete,
+            insert: new[insert].iter().collect(),
+        })
+        .collect()
+}
+
+/// Lines including their newline, and the offset each starts at with the
+/// total length on the end.
+fn lines(chars: &[char]) -> (Vec<&[char]>, Vec<usize>) {
+    let mut lines = vec![];
+    let mut starts = vec![0];
+
+    for line in chars.split_inclusive(|c| *c == '\n') {
+        lines.push(line);
+        starts.push(starts[starts.len() - 1] + line.len());
+    }
+
+    (lines, starts)
+}
+
+/// What lies between consecutive runs in either sequence.
+fn gaps(
+    runs: &[Run],
+    old: usize,
+    new: usize,
+) -> impl Iterator<Item = (Range<usize>, Range<usize>)> + '_ {
+    let end = Run {
+        old,
+        new,
+        length: 0,
+    };
+
+    runs.iter()
+        .copied()
+        .chain(iter::once(end))
+        .scan((0, 0), |at, run| {
+            let gap = (at.0..run.old, at.1..run.new);
+
+            *at = (run.old + run.length, run.new + run.length);
+
+            Some(gap)
+        })
+        .filter(|(old, new)| !old.is_empty() || !new.is_empty())
+}
+
+/// Myers' linear space divide and conquer: strips what both ends share,
+/// then splits the rest at the middle of an optimal edit path.
+fn conquer<T: PartialEq>(old: &[T], new: &[T], at_old: usize, at_new: usize, runs: &mut Vec<Run>) {
+    let prefix = common_prefix(old, new);
+
+    if prefix > 0 {
+        runs.push(Run {
+            old: at_old,
+            new: at_new,
+            length: prefix,
+        });
+    }
+
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let (at_old, at_new) = (at_old + prefix, at_new + prefix);
+
+    let suffix = common_suffix(old, new);
+
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+
+    if !old.is_empty() && !new.is_empty() {
+        if let Some((x, y)) = middle_snake(old, new) {
+            conquer(&old[..x], &new[..y], at_old, at_new, runs);
+            conquer(&old[x..], &new[y..], at_old + x, at_new + y, runs);
+        }
+    }
+
+    if suffix > 0 {
+        runs.push(Run {
+            old: at_old + old.len(),
+            new: at_new + new.len(),
+            length: suffix,
+        });
+    }
+}
+
+fn common_prefix<T: PartialEq>(old: &[T], new: &[T]) -> usize {
+    old.iter().zip(new).take_while(|(a, b)| a == b).count()
+}
+
+fn common_suffix<T: PartialEq>(old: &[T], new: &[T]) -> usize {
+    old.iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+/// Furthest x reached on each diagonal k = x - y.
+struct Frontier {
+    offset: isize,
+    x: Vec<usize>,
+}
+
+impl Frontier {
+    fn new(d: usize) -> Self {
+        Self {
+            offset: d as isize + 1,
+            x: vec![0; 2 * d + 3],
+        }
+    }
+}
+
+impl Index<isize> for Frontier {
+    type Output = usize;
+
+    fn index(&self, k: isize) -> &usize {
+        &self.x[(k + self.offset) as usize]
+    }
+}
+
+impl IndexMut<isize> for Frontier {
+    fn index_mut(&mut self, k: isize) -> &mut usize {
+        &mut self.x[(k + self.offset) as usize]
+    }
+}
+
+/// Searches from both corners at once until the paths meet, returning the
+/// point where the forward one starts its last snake. Past `LIMIT` it splits
+/// at whichever point either search got furthest to instead, as git does,
+/// which is no longer minimal but still keeps to what both texts share.
+fn middle_snake<T: PartialEq>(old: &[T], new: &[T]) -> Option<(usize, usize)> {
+    let (n, m) = (old.len(), new.len());
+    let delta = n as isize - m as isize;
+    let odd = delta & 1 == 1;
+
+    let most = ((n + m).div_ceil(2) + 1).min(LIMIT);
+
+    let mut forward = Frontier::new(most);
+    let mut backward = Frontier::new(most);
+
+    // The furthest point each search reached, by how far it is from its
+    // own corner.
+    let mut furthest = (0, (0, 0));
+
+    for d in 0..most as isize {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && forward[k - 1] < forward[k + 1]) {
+                forward[k + 1]
+            } else {
+                forward[k - 1] + 1
+            };
+
+            let y = (x as isize - k) as usize;
+            let start = (x, y);
+
+            if x < n && y < m {
+                x += common_prefix(&old[x..], &new[y..]);
+            }
+
+            forward[k] = x;
+
+            let reached = x as isize - k;
+
+            if x <= n && (0..=m as isize).contains(&reached) {
+                let y = reached as usize;
+
+                if x + y > furthest.0 {
+                    furthest = (x + y, (x, y));
+                }
+            }
+
+            if odd && (k - delta).abs() < d && forward[k] + backward[delta - k] >= n {
+                return Some(start);
+            }
+        }
+
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && backward[k - 1] < backward[k + 1]) {
+                backward[k + 1]
+            } else {
+                backward[k - 1] + 1
+            };
+
+            let mut y = (x as isize - k) as usize;
+
+            if x < n && y < m {
+                let suffix = common_suffix(&old[..n - x], &new[..m - y]);
+
+                x += suffix;
+                y += suffix;
+            }
+
+            backward[k] = x;
+
+            if x <= n && y <= m && x + y > furthest.0 {
+                furthest = (x + y, (n - x, m - y));
+            }
+
+            if !odd && (k - delta).abs() <= d && backward[k] + forward[delta - k] >= n {
+                return Some((n - x, m - y));
+            }
+        }
+    }
+
+    let (_, (x, y)) = furthest;
+
+    match (x, y) == (0, 0) || (x, y) == (n, m) {
+        true => None,
+        false => Some((x, y)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{buffer::fixtures::random, Buffer},
+    };
+
+    fn patch(old: &str, hunks: &[Hunk]) -> String {
+        let mut chars = old.chars().collect::<Vec<_>>();
+
+        for hunk in hunks.iter().rev() {
+            chars.splice(hunk.delete.clone(), hunk.insert.chars());
+        }
+
+        chars.into_iter().collect()
+    }
+
+    /// Chars deleted and inserted altogether.
+    fn cost(hunks: &[Hunk]) -> usize {
+        hunks
+            .iter()
+            .map(|hunk| hunk.delete.len() + hunk.insert.chars().count())
+            .sum()
+    }
+
+    fn longest_common_subsequence(old: &[char], new: &[char]) -> usize {
+        let mut lengths = vec![vec![0; new.len() + 1]; old.len() + 1];
+
+        for i in 0..old.len() {
+            for j in 0..new.len() {
+                lengths[i + 1][j + 1] = match old[i] == new[j] {
+                    true => lengths[i][j] + 1,
+                    false => lengths[i][j + 1].max(lengths[i + 1][j]),
+                };
+            }
+        }
+
+        lengths[old.len()][new.len()]
+    }
+
+    #[test]
+    fn diffs_are_minimal() {
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+
+        for _ in 0..500 {
+            let mut text = || {
+                let length = random(&mut state) % 40;
+
+                (0..length)
+                    .map(|_| (b'a' + (random(&mut state) % 4) as u8) as char)
+                    .collect::<String>()
+            };
+
+            let (old, new) = (text(), text());
+            let hunks = diff(&old, &new);
+
+            let (old_chars, new_chars) = (
+                old.chars().collect::<Vec<_>>(),
+                new.chars().collect::<Vec<_>>(),
+            );
+
+            let common = longest_common_subsequence(&old_chars, &new_chars);
+
+            assert_eq!(patch(&old, &hunks), new);
+            assert_eq!(cost(&hunks), old_chars.len() + new_chars.len() - 2 * common);
+        }
+    }
+
+    #[test]
+    fn unchanged_text_has_no_hunks() {
+        assert!(diff("same\ntext\n", "same\ntext\n").is_empty());
+        assert!(diff("", "").is_empty());
+        assert_eq!(
+            diff("", "new"),
+            vec![Hunk {
+                delete: 0..0,
+                insert: "new".into()
+            }]
+        );
+    }
+
+    /// Lines of a large file with a char changed on every other one, many
+    /// more edits than `LIMIT`.
+    fn scattered(lines: usize) -> (String, String) {
+        let old = (0..lines)
+            .map(|line| format!("line {:05} of a large file\n", line))
+            .collect::<String>();
+
+        let new = (0..lines)
+            .map(|line| match line % 2 {
+                0 => format!("line {:05} of a LARGE file\n", line),
+                _ => format!("line {:05} of a large file\n", line),
+            })
+            .collect::<String>();
+
+        (old, new)
+    }
+
+    #[test]
+    fn many_edits_stay_apart() {
+        // Small enough to be diffed by char throughout.
+        let (old, new) = scattered(1000);
+
+        assert!(old.len() + new.len() <= REFINE);
+
+        let hunks = diff(&old, &new);
+
+        assert_eq!(patch(&old, &hunks), new);
+        assert!(hunks.len() >= 500);
+        assert!(cost(&hunks) < old.len() / 2);
+
+        // Large enough to be diffed by line first.
+        let (old, new) = scattered(3000);
+
+        assert!(old.len() + new.len() > REFINE);
+
+        let hunks = diff(&old, &new);
+
+        assert_eq!(patch(&old, &hunks), new);
+        assert!(hunks.len() >= 1500);
+        assert!(cost(&hunks) < old.len() / 2);
+    }
+
+    #[test]
+    fn marks_survive_importing_a_large_file_with_many_edits() {
+        let (old, new) = scattered(3000);
+        let mut buffer = Buffer::new();
+
+        buffer.insert(0, &old, 0).unwrap();
+
+        // The start of an edited line near the end, past every other edit.
+        let line = old.find("line 02998").unwrap();
+
+        buffer.set_mark("late", line, 0).unwrap();
+        buffer.import(&new, 1).unwrap();
+
+        assert_eq!(buffer.to_string(), new);
+        assert_eq!(buffer.mark("late").unwrap(), Some(line));
+    }
+}
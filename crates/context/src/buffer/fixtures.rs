@@ -0,0 +1,26 @@
+use crate::{Buffer, CmRDT};
+
+/// A buffer holding `text`, with nothing to undo.
+pub fn buffer(text: &str) -> Buffer {
+    let mut buffer = Buffer::new();
+
+    buffer.insert(0, text, 0).unwrap();
+    buffer.forget().unwrap();
+    buffer
+}
+
+/// Hands every op `from` has applied since the last call to `to`.
+pub fn replicate(from: &Buffer, to: &mut Buffer) {
+    for op in from.drain().unwrap() {
+        to.validate_op(&op).unwrap();
+        to.apply(op);
+    }
+}
+
+/// A small xorshift, so random tests are the same every run.
+pub fn random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
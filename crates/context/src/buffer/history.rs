@@ -0,0 +1,289 @@
+use super::{index::Id, selection::Span, Actor};
+
+use ::{
+    chrono::{DateTime, Utc},
+    std::{
+        collections::{BTreeMap, HashMap, HashSet},
+        time::Duration,
+    },
+};
+
+/// Edits of the same kind made in quick succession, without the cursor
+/// moving in between, undo together.
+static COALESCE: Duration = Duration::from_millis(1000);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Group {
+    Type,
+    Space,
+    Delete,
+    Edit,
+}
+
+impl Group {
+    /// Typing runs until the whitespace that ends a word, and deleting runs
+    /// for as long as it goes on.
+    fn continues(self, next: Group) -> bool {
+        matches!(
+            (self, next),
+            (Group::Type, Group::Type)
+                | (Group::Type, Group::Space)
+                | (Group::Delete, Group::Delete)
+        )
+    }
+}
+
+/// Runs of elements that sat next to each other when they were deleted.
+pub type Run = Vec<(Id, char)>;
+
+/// What one logical edit did to the list, by identifier rather than offset
+/// so it can still be reverted after others have edited around it.
+#[derive(Debug, Clone, Default)]
+pub struct Transaction {
+    pub inserted: HashSet<Id>,
+    pub deleted: Vec<Run>,
+    pub selections: Vec<Span>,
+}
+
+impl Transaction {
+    fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.deleted.is_empty()
+    }
+}
+
+#[derive(Default)]
+struct Stacks {
+    undo: Vec<Transaction>,
+    redo: Vec<Transaction>,
+    depth: usize,
+    group: Option<Group>,
+    last: Option<(Group, DateTime<Utc>)>,
+    aliases: HashMap<Id, Id>,
+}
+
+/// Undo and redo stacks kept separately for every actor, so undoing only
+/// ever reverts the caller's own edits.
+#[derive(Default)]
+pub struct History {
+    actors: BTreeMap<Actor, Stacks>,
+}
+
+impl History {
+    /// Opens a transaction, or joins the last one when `group` continues it.
+    /// Nested calls join whatever is already open.
+    pub fn begin(&mut self, actor: Actor, group: Group, selections: Vec<Span>) {
+        let stacks = self.actors.entry(actor).or_default();
+
+        stacks.depth += 1;
+
+        if stacks.depth > 1 {
+            return;
+        }
+
+        let continues = match stacks.last {
+            Some((last, at)) => {
+                last.continues(group)
+                    && (Utc::now() - at)
+                        .to_std()
+                        .is_ok_and(|elapsed| elapsed < COALESCE)
+                    && stacks.redo.is_empty()
+            }
+            None => false,
+        };
+
+        if !continues || stacks.undo.is_empty() {
+            stacks.undo.push(Transaction {
+                selections,
+                ..Transaction::default()
+            });
+        }
+
+        stacks.group = Some(group);
+    }
+
+    pub fn end(&mut self, actor: Actor) {
+        let stacks = self.actors.entry(actor).or_default();
+
+        stacks.depth = stacks.depth.saturating_sub(1);
+
+        if stacks.depth > 0 {
+            return;
+        }
+
+        if stacks.undo.last().is_some_and(Transaction::is_empty) {
+            stacks.undo.pop();
+        }
+
+        stacks.last = stacks.group.take().map(|group| (group, Utc::now()));
+    }
+
+    /// Cursor movement between edits starts a new transaction.
+    pub fn moved(&mut self, actor: Actor) {
+        if let Some(stacks) = self.actors.get_mut(&actor) {
+            if stacks.depth == 0 {
+                stacks.last = None;
+            }
+        }
+    }
+
+    fn open(&mut self, actor: Actor) -> Option<&mut Transaction> {
+        let stacks = self.actors.get_mut(&actor)?;
+
+        if stacks.depth == 0 {
+            return None;
+        }
+
+        stacks.redo.clear();
+        stacks.undo.last_mut()
+    }
+
+    pub fn inserted(&mut self, actor: Actor, id: Id) {
+        if let Some(transaction) = self.open(actor) {
+            transaction.inserted.insert(id);
+        }
+    }
+
+    /// Deleting what this same transaction inserted cancels out.
+    pub fn deleted(&mut self, actor: Actor, run: Run) {
+        if let Some(transaction) = self.open(actor) {
+            let run = run
+                .into_iter()
+                .filter(|(id, _)| !transaction.inserted.remove(id))
+                .collect::<Run>();
+
+            if !run.is_empty() {
+                transaction.deleted.push(run);
+            }
+        }
+    }
+
+    pub fn undo(&mut self, actor: Actor) -> Option<Transaction> {
+        let stacks = self.actors.get_mut(&actor)?;
+
+        stacks.last = None;
+        stacks.undo.pop()
+    }
+
+    pub fn redo(&mut self, actor: Actor) -> Option<Transaction> {
+        let stacks = self.actors.get_mut(&actor)?;
+
+        stacks.last = None;
+        stacks.redo.pop()
+    }
+
+    pub fn undone(&mut self, actor: Actor, transaction: Transaction) {
+        self.actors.entry(actor).or_default().redo.push(transaction);
+    }
+
+    pub fn redone(&mut self, actor: Actor, transaction: Transaction) {
+        self.actors.entry(actor).or_default().undo.push(transaction);
+    }
+
+    /// Records that a deleted element came back under a new identifier.
+    pub fn alias(&mut self, actor: Actor, from: Id, to: Id) {
+        self.actors
+            .entry(actor)
+            .or_default()
+            .aliases
+            .insert(from, to);
+    }
+
+    /// The identifier an element lives under now, after any restores.
+    pub fn resolve(&self, actor: Actor, mut id: Id) -> Id {
+        if let Some(stacks) = self.actors.get(&actor) {
+            while let Some(alias) = stacks.aliases.get(&id) {
+                id = alias.clone();
+            }
+        }
+
+        id
+    }
+
+    pub fn clear(&mut self) {
+        self.actors.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{buffer::fixtures::buffer, Buffer, Selection};
+
+    #[test]
+    fn undo_reverts_only_the_callers_edits() {
+        let mut buffer = Buffer::new();
+
+        buffer.write("hello", 0).unwrap();
+        buffer.insert(0, "X ", 1).unwrap();
+        buffer.insert(7, " Y", 1).unwrap();
+
+        assert_eq!(buffer.to_string(), "X hello Y");
+        assert!(buffer.undo(0).unwrap());
+        assert_eq!(buffer.to_string(), "X  Y");
+        assert!(buffer.redo(0).unwrap());
+        assert_eq!(buffer.to_string(), "X hello Y");
+        assert!(!buffer.undo(1).unwrap());
+    }
+
+    #[test]
+    fn undo_restores_deletes_among_later_edits() {
+        let mut buffer = buffer("one two three");
+
+        buffer.transact(0, |buffer| buffer.delete(4..8, 0)).unwrap();
+        buffer.insert(0, ">", 1).unwrap();
+        buffer.insert(10, "<", 1).unwrap();
+
+        assert_eq!(buffer.to_string(), ">one three<");
+        assert!(buffer.undo(0).unwrap());
+        assert_eq!(buffer.to_string(), ">one two three<");
+        assert!(buffer.redo(0).unwrap());
+        assert_eq!(buffer.to_string(), ">one three<");
+        assert!(buffer.undo(0).unwrap());
+        assert!(buffer.undo(0).is_ok_and(|undone| !undone));
+        assert_eq!(buffer.to_string(), ">one two three<");
+    }
+
+    #[test]
+    fn typing_undoes_a_word_at_a_time() {
+        let mut buffer = Buffer::new();
+
+        for text in ["a", "b", " ", "c", "d"] {
+            buffer.write(text, 0).unwrap();
+        }
+
+        buffer.undo(0).unwrap();
+        assert_eq!(buffer.to_string(), "ab ");
+
+        buffer.undo(0).unwrap();
+        assert_eq!(buffer.to_string(), "");
+    }
+
+    #[test]
+    fn moving_the_cursor_ends_a_transaction() {
+        let mut buffer = Buffer::new();
+
+        buffer.write("ab", 0).unwrap();
+        buffer.step(-1, 0).unwrap();
+        buffer.write("x", 0).unwrap();
+
+        assert_eq!(buffer.to_string(), "axb");
+
+        buffer.undo(0).unwrap();
+        assert_eq!(buffer.to_string(), "ab");
+    }
+
+    #[test]
+    fn undo_puts_the_selections_back() {
+        let mut buffer = buffer("one two");
+
+        buffer.select(4..7, 0).unwrap();
+        buffer.write("2", 0).unwrap();
+
+        assert_eq!(buffer.to_string(), "one 2");
+        assert_eq!(buffer.selection(0).unwrap(), Selection::caret(5));
+
+        buffer.undo(0).unwrap();
+
+        assert_eq!(buffer.to_string(), "one two");
+        assert_eq!(buffer.selection(0).unwrap(), Selection::from(4..7));
+    }
+}
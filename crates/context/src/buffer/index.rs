@@ -0,0 +1,529 @@
+use super::{Actor, Change};
+
+use ::{
+    crdts::{list, CmRDT, Identifier, List, OrdDot, VClock},
+    serde::{Deserialize, Serialize},
+    std::{
+        cmp::Ordering,
+        collections::hash_map::DefaultHasher,
+        hash::{Hash, Hasher},
+        ops::Range,
+    },
+};
+
+pub type Id = Identifier<OrdDot<Actor>>;
+
+type Link = Option<Box<Node>>;
+
+/// A position held just after an element, or at the very start, so it keeps
+/// its place while others edit around it. Once its element is deleted it
+/// settles where that element used to be.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Anchor(Option<Id>);
+
+impl Anchor {
+    pub(crate) fn map(self, f: impl FnOnce(Id) -> Id) -> Self {
+        Self(self.0.map(f))
+    }
+}
+
+/// One side of the gap at an offset: `After` the element before it, as an
+/// `Anchor` is, or `Before` the element after it, `None` being the very end.
+/// Text inserted into the gap later lands past an `After` edge and short of
+/// a `Before` one, which is what lets a span choose whether it grows.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Edge {
+    After(Anchor),
+    Before(Option<Id>),
+}
+
+struct Node {
+    id: Id,
+    value: char,
+    priority: u64,
+    size: usize,
+    lines: usize,
+    left: Link,
+    right: Link,
+}
+
+impl Node {
+    fn new(id: Id, value: char) -> Box<Node> {
+        let mut hasher = DefaultHasher::new();
+
+        id.hash(&mut hasher);
+
+        Box::new(Node {
+            priority: hasher.finish(),
+            size: 1,
+            lines: (value == '\n') as usize,
+            id,
+            value,
+            left: None,
+            right: None,
+        })
+    }
+
+    fn update(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+        self.lines = (self.value == '\n') as usize + lines(&self.left) + lines(&self.right);
+    }
+}
+
+fn size(link: &Link) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+fn lines(link: &Link) -> usize {
+    link.as_ref().map_or(0, |node| node.lines)
+}
+
+fn merge(left: Link, right: Link) -> Link {
+    match (left, right) {
+        (None, right) => right,
+        (left, None) => left,
+        (Some(mut left), Some(mut right)) => {
+            if left.priority > right.priority {
+                left.right = merge(left.right.take(), Some(right));
+                left.update();
+                Some(left)
+            } else {
+                right.left = merge(Some(left), right.left.take());
+                right.update();
+                Some(right)
+            }
+        }
+    }
+}
+
+/// Splits into the nodes ordered before `id` and the rest.
+fn split(link: Link, id: &Id) -> (Link, Link) {
+    match link {
+        None => (None, None),
+        Some(mut node) => {
+            if node.id < *id {
+                let (left, right) = split(node.right.take(), id);
+                node.right = left;
+                node.update();
+                (Some(node), right)
+            } else {
+                let (left, right) = split(node.left.take(), id);
+                node.left = right;
+                node.update();
+                (left, Some(node))
+            }
+        }
+    }
+}
+
+fn split_first(link: Link) -> (Link, Link) {
+    match link {
+        None => (None, None),
+        Some(mut node) => match node.left.take() {
+            None => {
+                let right = node.right.take();
+                node.update();
+                (Some(node), right)
+            }
+            left => {
+                let (first, rest) = split_first(left);
+                node.left = rest;
+                node.update();
+                (first, Some(node))
+            }
+        },
+    }
+}
+
+fn collect(link: &Link, base: usize, range: &Range<usize>, text: &mut String) {
+    if let Some(node) = link {
+        let index = base + size(&node.left);
+
+        if range.start < index {
+            collect(&node.left, base, range, text);
+        }
+
+        if range.contains(&index) {
+            text.push(node.value);
+        }
+
+        if range.end > index + 1 {
+            collect(&node.right, index + 1, range, text);
+        }
+    }
+}
+
+/// Balanced tree over the list's identifiers that counts chars and newlines
+/// per subtree, so positions, lines and slices resolve in logarithmic time
+/// instead of walking the whole `List`.
+#[derive(Default)]
+pub struct Index {
+    root: Link,
+    clock: VClock<Actor>,
+}
+
+impl Index {
+    pub fn from_list(list: &List<char, Actor>, clock: VClock<Actor>) -> Self {
+        let mut index = Index { root: None, clock };
+
+        for (id, value) in list.iter_entries() {
+            index.root = merge(index.root.take(), Some(Node::new(id.clone(), *value)));
+        }
+
+        index
+    }
+
+    pub fn clock(&self) -> &VClock<Actor> {
+        &self.clock
+    }
+
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    /// Builds the op inserting `value` at `offset`, mirroring
+    /// `List::insert_index` without its linear scan.
+    pub fn insert_op(&self, offset: usize, value: char, actor: Actor) -> list::Op<char, Actor> {
+        let offset = offset.min(self.len());
+
+        let previous = offset.checked_sub(1).and_then(|offset| self.select(offset));
+        let next = self.select(offset);
+
+        let dot = self.clock.inc(actor);
+        let id = Identifier::between(previous, next, dot.into());
+
+        list::Op::Insert { id, val: value }
+    }
+
+    pub fn delete_op(&self, offset: usize, actor: Actor) -> Option<list::Op<char, Actor>> {
+        self.select(offset).map(|id| self.delete_id_op(id, actor))
+    }
+
+    pub fn delete_id_op(&self, id: &Id, actor: Actor) -> list::Op<char, Actor> {
+        list::Op::Delete {
+            id: id.clone(),
+            dot: self.clock.inc(actor),
+        }
+    }
+
+    /// Follows `op` once `list` has applied it, returning the edit it made
+    /// if it made one.
+    pub fn observe(
+        &mut self,
+        op: &list::Op<char, Actor>,
+        list: &List<char, Actor>,
+    ) -> Option<Change> {
+        self.clock.apply(op.dot());
+
+        match op {
+            list::Op::Insert { id, val } => {
+                if list.get(id).is_none() || self.contains(id) {
+                    return None;
+                }
+
+                let offset = self.rank(id);
+                let (left, right) = split(self.root.take(), id);
+
+                self.root = merge(merge(left, Some(Node::new(id.clone(), *val))), right);
+
+                Some(Change::Edited {
+                    deleted: offset..offset,
+                    inserted: 1,
+                })
+            }
+            list::Op::Delete { id, .. } => {
+                if !self.contains(id) {
+                    return None;
+                }
+
+                let offset = self.rank(id);
+                let (left, right) = split(self.root.take(), id);
+                let (_, right) = split_first(right);
+
+                self.root = merge(left, right);
+
+                Some(Change::Edited {
+                    deleted: offset..offset + 1,
+                    inserted: 0,
+                })
+            }
+        }
+    }
+
+    pub fn contains(&self, id: &Id) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn get(&self, id: &Id) -> Option<char> {
+        let mut link = &self.root;
+
+        while let Some(node) = link {
+            match id.cmp(&node.id) {
+                Ordering::Less => link = &node.left,
+                Ordering::Greater => link = &node.right,
+                Ordering::Equal => return Some(node.value),
+            }
+        }
+
+        None
+    }
+
+    /// Count of elements ordered before `id`, whether or not `id` is present.
+    pub fn rank(&self, id: &Id) -> usize {
+        let mut rank = 0;
+        let mut link = &self.root;
+
+        while let Some(node) = link {
+            if node.id < *id {
+                rank += size(&node.left) + 1;
+                link = &node.right;
+            } else {
+                link = &node.left;
+            }
+        }
+
+        rank
+    }
+
+    pub fn anchor(&self, offset: usize) -> Anchor {
+        Anchor(
+            offset
+                .checked_sub(1)
+                .and_then(|offset| self.select(offset))
+                .cloned(),
+        )
+    }
+
+    pub fn resolve(&self, anchor: &Anchor) -> usize {
+        match &anchor.0 {
+            Some(id) => self.rank(id) + self.contains(id) as usize,
+            None => 0,
+        }
+    }
+
+    pub fn edge(&self, offset: usize, before: bool) -> Edge {
+        match before {
+            true => Edge::Before(self.select(offset).cloned()),
+            false => Edge::After(self.anchor(offset)),
+        }
+    }
+
+    pub fn resolve_edge(&self, edge: &Edge) -> usize {
+        match edge {
+            Edge::After(anchor) => self.resolve(anchor),
+            Edge::Before(Some(id)) => self.rank(id),
+            Edge::Before(None) => self.len(),
+        }
+    }
+
+    pub fn select(&self, mut offset: usize) -> Option<&Id> {
+        let mut link = &self.root;
+
+        while let Some(node) = link {
+            let left = size(&node.left);
+
+            match offset.cmp(&left) {
+                Ordering::Less => link = &node.left,
+                Ordering::Equal => return Some(&node.id),
+                Ordering::Greater => {
+                    offset -= left + 1;
+                    link = &node.right;
+                }
+            }
+        }
+
+        None
+    }
+
+    pub fn line_count(&self) -> usize {
+        lines(&self.root) + 1
+    }
+
+    /// Zero based line holding `offset`, i.e. the newlines before it.
+    pub fn line_of(&self, mut offset: usize) -> usize {
+        let mut line = 0;
+        let mut link = &self.root;
+
+        while let Some(node) = link {
+            let left = size(&node.left);
+
+            if offset <= left {
+                link = &node.left;
+            } else {
+                line += lines(&node.left) + (node.value == '\n') as usize;
+                offset -= left + 1;
+                link = &node.right;
+            }
+        }
+
+        line
+    }
+
+    /// Offset of the first char on `line`, if the document has that many.
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        if line == 0 {
+            return Some(0);
+        }
+
+        let mut remaining = line;
+        let mut base = 0;
+        let mut link = &self.root;
+
+        while let Some(node) = link {
+            let before = lines(&node.left);
+
+            if remaining <= before {
+                link = &node.left;
+                continue;
+            }
+
+            remaining -= before;
+            base += size(&node.left);
+
+            if node.value == '\n' {
+                if remaining == 1 {
+                    return Some(base + 1);
+                }
+
+                remaining -= 1;
+            }
+
+            base += 1;
+            link = &node.right;
+        }
+
+        None
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> String {
+        let mut text = String::new();
+
+        collect(&self.root, 0, &range, &mut text);
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::buffer::fixtures::random};
+
+    fn check(index: &Index, list: &List<char, Actor>) {
+        let text = list.read::<String>();
+        let ids = list.iter_entries().map(|(id, _)| id).collect::<Vec<_>>();
+
+        assert_eq!(index.len(), ids.len());
+        assert_eq!(index.slice(0..index.len()), text);
+        assert_eq!(index.line_count(), text.matches('\n').count() + 1);
+
+        for (offset, id) in ids.iter().enumerate() {
+            assert_eq!(index.select(offset), Some(*id));
+            assert_eq!(index.rank(id), offset);
+        }
+
+        assert_eq!(index.select(ids.len()), None);
+
+        let chars = text.chars().collect::<Vec<_>>();
+
+        for offset in 0..=chars.len() {
+            let line = chars[..offset].iter().filter(|c| **c == '\n').count();
+
+            assert_eq!(index.line_of(offset), line);
+        }
+
+        let mut starts = vec![0];
+        starts.extend(
+            chars
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| **c == '\n')
+                .map(|(offset, _)| offset + 1),
+        );
+
+        for (line, start) in starts.iter().enumerate() {
+            assert_eq!(index.line_start(line), Some(*start));
+        }
+
+        assert_eq!(index.line_start(starts.len()), None);
+    }
+
+    #[test]
+    fn follows_the_list_through_random_edits() {
+        let mut list = List::new();
+        let mut index = Index::default();
+        let mut state = 0x2545_f491_4f6c_dd1d;
+
+        for step in 0..2000 {
+            let roll = random(&mut state);
+            let actor = (roll % 3) as Actor;
+
+            let op = match index.len() > 0 && roll % 5 < 2 {
+                true => {
+                    let offset = (random(&mut state) as usize) % index.len();
+
+                    index.delete_op(offset, actor).unwrap()
+                }
+                false => {
+                    let offset = (random(&mut state) as usize) % (index.len() + 1);
+                    let value = match roll % 7 {
+                        0 => '\n',
+                        n => (b'a' + n as u8) as char,
+                    };
+
+                    index.insert_op(offset, value, actor)
+                }
+            };
+
+            list.apply(op.clone());
+
+            assert!(index.observe(&op, &list).is_some());
+            assert!(index.observe(&op, &list).is_none());
+
+            if step % 100 == 0 {
+                check(&index, &list);
+            }
+        }
+
+        check(&index, &list);
+
+        let rebuilt = Index::from_list(&list, index.clock().clone());
+
+        check(&rebuilt, &list);
+    }
+
+    #[test]
+    fn reports_the_offsets_it_edits() {
+        let mut list = List::new();
+        let mut index = Index::default();
+
+        for (offset, value) in "abc".chars().enumerate() {
+            let op = index.insert_op(offset, value, 0);
+
+            list.apply(op.clone());
+            index.observe(&op, &list);
+        }
+
+        let op = index.insert_op(1, 'x', 1);
+        list.apply(op.clone());
+
+        assert_eq!(
+            index.observe(&op, &list),
+            Some(Change::Edited {
+                deleted: 1..1,
+                inserted: 1
+            })
+        );
+
+        let op = index.delete_op(2, 0).unwrap();
+        list.apply(op.clone());
+
+        assert_eq!(
+            index.observe(&op, &list),
+            Some(Change::Edited {
+                deleted: 2..3,
+                inserted: 0
+            })
+        );
+        assert_eq!(index.slice(0..index.len()), "axc");
+    }
+}
@@ -0,0 +1,17 @@
+use ::serde::{Deserialize, Serialize};
+
+/// A place in the text as a zero based line and the chars before it on that
+/// line.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, column: usize) -> Self {
+        Self { line, column }
+    }
+}
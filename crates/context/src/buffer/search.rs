@@ -0,0 +1,272 @@
+use ::{
+    regex::{CaptureLocations, Regex, RegexBuilder},
+    serde::{Deserialize, Serialize},
+    std::ops::Range,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Case {
+    Sensitive,
+    Insensitive,
+    /// Ignores case unless the pattern has an uppercase letter in it.
+    #[default]
+    Smart,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    #[default]
+    Forward,
+    Backward,
+}
+
+/// What to look for: `pattern` is taken as is unless `regex` is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Query {
+    pub pattern: String,
+    pub regex: bool,
+    pub case: Case,
+    pub whole_word: bool,
+}
+
+impl Query {
+    pub fn literal(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn regex(pattern: &str) -> Self {
+        Self {
+            regex: true,
+            ..Self::literal(pattern)
+        }
+    }
+
+    pub fn case(self, case: Case) -> Self {
+        Self { case, ..self }
+    }
+
+    pub fn whole_word(self, whole_word: bool) -> Self {
+        Self { whole_word, ..self }
+    }
+
+    pub(crate) fn compile(&self) -> Result<Regex, regex::Error> {
+        let pattern = match self.regex {
+            true => self.pattern.clone(),
+            false => regex::escape(&self.pattern),
+        };
+
+        let insensitive = match self.case {
+            Case::Sensitive => false,
+            Case::Insensitive => true,
+            Case::Smart => !self.pattern.chars().any(char::is_uppercase),
+        };
+
+        RegexBuilder::new(&pattern)
+            .case_insensitive(insensitive)
+            .multi_line(true)
+            .build()
+    }
+}
+
+/// A match as char offsets into the text, with the byte offsets of each
+/// group for expanding a replacement.
+pub(super) struct Found {
+    pub range: Range<usize>,
+    groups: CaptureLocations,
+}
+
+/// Every non empty match in `text`, in order. Whole word queries skip past
+/// matches that run into a word on either side and try again one char on,
+/// so a rejected match cannot hide one overlapping it.
+pub(super) fn matches(query: &Query, regex: &Regex, text: &str) -> Vec<Found> {
+    let mut found = vec![];
+
+    let mut at = 0;
+    let mut chars = 0;
+    let mut counted = 0;
+
+    let mut groups = regex.capture_locations();
+
+    while let Some(next) = regex.captures_read_at(&mut groups, text, at) {
+        let rejected =
+            next.start() == next.end() || (query.whole_word && !bounded(text, next.range()));
+
+        if rejected {
+            match text[next.start()..].chars().next() {
+                Some(c) => at = next.start() + c.len_utf8(),
+                None => break,
+            }
+
+            continue;
+        }
+
+        chars += text[counted..next.start()].chars().count();
+
+        let start = chars;
+
+        chars += next.as_str().chars().count();
+        counted = next.end();
+
+        found.push(Found {
+            range: start..chars,
+            groups: groups.clone(),
+        });
+
+        at = next.end();
+    }
+
+    found
+}
+
+fn bounded(text: &str, range: Range<usize>) -> bool {
+    let word = |c: char| c.is_alphanumeric() || c == '_';
+
+    let before = text[..range.start].chars().next_back();
+    let after = text[range.end..].chars().next();
+
+    !before.is_some_and(word) && !after.is_some_and(word)
+}
+
+/// What `found` is replaced with: `replacement` as is for literal queries,
+/// and with `$1`, `${name}` and `$$` filled in from the match for regex ones.
+pub(super) fn expand(
+    query: &Query,
+    regex: &Regex,
+    text: &str,
+    found: &Found,
+    replacement: &str,
+) -> String {
+    if !query.regex {
+        return replacement.into();
+    }
+
+    let group = |name: &str| {
+        let index = match name.parse::<usize>() {
+            Ok(index) => Some(index),
+            Err(_) => regex.capture_names().position(|n| n == Some(name)),
+        };
+
+        index
+            .and_then(|index| found.groups.get(index))
+            .map_or("", |(start, end)| &text[start..end])
+    };
+
+    let mut expanded = String::new();
+    let mut rest = replacement;
+
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        rest = &rest[dollar + 1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+        } else if let Some((name, after)) = rest
+            .strip_prefix('{')
+            .and_then(|braced| braced.split_once('}'))
+        {
+            expanded.push_str(group(name));
+            rest = after;
+        } else {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+
+            match length {
+                0 => expanded.push('$'),
+                _ => expanded.push_str(group(&rest[..length])),
+            }
+
+            rest = &rest[length..];
+        }
+    }
+
+    expanded.push_str(rest);
+
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(query: &Query, text: &str) -> Vec<Range<usize>> {
+        let regex = query.compile().unwrap();
+
+        matches(query, &regex, text)
+            .into_iter()
+            .map(|found| found.range)
+            .collect()
+    }
+
+    #[test]
+    fn literal_patterns_are_not_regexes() {
+        assert_eq!(ranges(&Query::literal("a.c"), "abc a.c"), vec![4..7]);
+        assert_eq!(ranges(&Query::regex("a.c"), "abc a.c"), vec![0..3, 4..7]);
+    }
+
+    #[test]
+    fn matches_count_chars_rather_than_bytes() {
+        assert_eq!(
+            ranges(&Query::literal("é"), "été é"),
+            vec![0..1, 2..3, 4..5]
+        );
+    }
+
+    #[test]
+    fn smart_case_only_ignores_case_for_lowercase_patterns() {
+        let text = "Word word WORD";
+
+        assert_eq!(ranges(&Query::literal("word"), text).len(), 3);
+        assert_eq!(ranges(&Query::literal("Word"), text), vec![0..4]);
+        assert_eq!(
+            ranges(&Query::literal("word").case(Case::Sensitive), text),
+            vec![5..9]
+        );
+        assert_eq!(
+            ranges(&Query::literal("Word").case(Case::Insensitive), text).len(),
+            3
+        );
+    }
+
+    #[test]
+    fn whole_words_skip_matches_inside_words() {
+        let query = Query::literal("cat").whole_word(true);
+
+        assert_eq!(
+            ranges(&query, "cat concat cats cat_ cat"),
+            vec![0..3, 21..24]
+        );
+
+        // The rejected "aa" at 0 must not hide the word starting at 1.
+        let query = Query::regex("a+b?").whole_word(true);
+
+        assert_eq!(ranges(&query, "aaab ab"), vec![0..4, 5..7]);
+    }
+
+    #[test]
+    fn empty_matches_are_skipped() {
+        assert_eq!(ranges(&Query::regex("x*"), "axxb"), vec![1..3]);
+    }
+
+    #[test]
+    fn replacements_fill_in_groups() {
+        let query = Query::regex(r"(?P<key>\w+)=(\w+)");
+        let regex = query.compile().unwrap();
+        let text = "a=1";
+
+        let found = &matches(&query, &regex, text)[0];
+        let expand = |replacement| expand(&query, &regex, text, found, replacement);
+
+        assert_eq!(expand("$2=$key"), "1=a");
+        assert_eq!(expand("${2}x ${key}y"), "1x ay");
+        assert_eq!(expand("$$1 $ $missing"), "$1 $ ");
+
+        let literal = Query::literal("a=1");
+
+        assert_eq!(super::expand(&literal, &regex, text, found, "$1"), "$1");
+    }
+}
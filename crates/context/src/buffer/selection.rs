@@ -0,0 +1,94 @@
+use super::{Anchor, Cursor};
+
+use ::{
+    serde::{Deserialize, Serialize},
+    std::ops::Range,
+};
+
+/// A caret when `anchor == head`, otherwise the text between them. The head
+/// is the end that moves when the selection is extended.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct Selection {
+    pub anchor: Cursor,
+    pub head: Cursor,
+}
+
+impl Selection {
+    pub fn caret(cursor: Cursor) -> Self {
+        Self {
+            anchor: cursor,
+            head: cursor,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    pub fn start(&self) -> Cursor {
+        self.anchor.min(self.head)
+    }
+
+    pub fn end(&self) -> Cursor {
+        self.anchor.max(self.head)
+    }
+
+    pub fn range(&self) -> Range<Cursor> {
+        self.start()..self.end()
+    }
+
+    pub fn collapse(&self) -> Self {
+        Self::caret(self.head)
+    }
+}
+
+/// What is kept in the register: both ends pinned to list elements, turned
+/// back into a `Selection` against the current text when read.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Span {
+    pub anchor: Anchor,
+    pub head: Anchor,
+}
+
+impl From<Range<Cursor>> for Selection {
+    fn from(range: Range<Cursor>) -> Self {
+        Self {
+            anchor: range.start,
+            head: range.end,
+        }
+    }
+}
+
+/// Sorts selections and merges the ones that overlap or touch, keeping the
+/// direction of whichever comes first.
+pub(crate) fn normalize(mut selections: Vec<Selection>) -> Vec<Selection> {
+    selections.sort_by_key(|selection| (selection.start(), selection.end()));
+
+    let mut normalized: Vec<Selection> = Vec::with_capacity(selections.len());
+
+    for selection in selections {
+        match normalized.last_mut() {
+            Some(last)
+                if selection.start() < last.end()
+                    || (selection.start() == last.end()
+                        && (selection.is_empty() || last.is_empty())) =>
+            {
+                let (start, end) = (last.start(), last.end().max(selection.end()));
+
+                *last = if last.anchor <= last.head {
+                    Selection::from(start..end)
+                } else {
+                    Selection {
+                        anchor: end,
+                        head: start,
+                    }
+                };
+            }
+            _ => normalized.push(selection),
+        }
+    }
+
+    normalized
+}
@@ -0,0 +1,54 @@
+use super::{index::Edge, Actor};
+
+use ::{
+    serde::{Deserialize, Serialize},
+    std::ops::Range,
+};
+
+/// Formatting or meaning attached to a span of text.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Style {
+    Bold,
+    Italic,
+    Underline,
+    Highlight,
+    Link(String),
+    Diagnostic(String),
+}
+
+impl Style {
+    /// Whether text typed at the end of the span takes the style on, as it
+    /// does for bold but shouldn't for a link or a diagnostic.
+    pub fn expands(&self) -> bool {
+        match self {
+            Style::Bold | Style::Italic | Style::Underline | Style::Highlight => true,
+            Style::Link(_) | Style::Diagnostic(_) => false,
+        }
+    }
+}
+
+/// Names a span for as long as it lives: the actor that made it and how
+/// many changes to the spans it had made by then.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Tag {
+    pub actor: Actor,
+    pub counter: u64,
+}
+
+/// What is kept in the register: the style and the edges of its span. Text
+/// inserted at the start stays outside, and text inserted at the end comes
+/// in only if the style expands.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Styled {
+    pub(super) style: Style,
+    pub(super) start: Edge,
+    pub(super) end: Edge,
+}
+
+/// A span resolved against the current text.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Annotation {
+    pub tag: Tag,
+    pub style: Style,
+    pub range: Range<usize>,
+}
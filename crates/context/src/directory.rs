@@ -0,0 +1,311 @@
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Io {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+}
+
+use ::{
+    chrono::{DateTime, Local},
+    std::{
+        collections::BTreeSet,
+        fmt::{self, Display},
+        fs::{read_dir, symlink_metadata, Metadata},
+        path::{Path, PathBuf},
+        time::SystemTime,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Directory,
+    File,
+    Link,
+    Other,
+}
+
+impl From<&Metadata> for Kind {
+    fn from(metadata: &Metadata) -> Self {
+        let kind = metadata.file_type();
+
+        if kind.is_dir() {
+            Kind::Directory
+        } else if kind.is_file() {
+            Kind::File
+        } else if kind.is_symlink() {
+            Kind::Link
+        } else {
+            Kind::Other
+        }
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Kind::Directory => "directory",
+            Kind::File => "file",
+            Kind::Link => "link",
+            Kind::Other => "other",
+        };
+
+        f.pad(name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub depth: usize,
+    pub kind: Kind,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub expanded: bool,
+}
+
+impl Entry {
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+
+    fn label(&self) -> String {
+        let marker = match (self.kind, self.expanded) {
+            (Kind::Directory, true) => "▾ ",
+            (Kind::Directory, false) => "▸ ",
+            _ => "  ",
+        };
+
+        format!("{}{}{}", "  ".repeat(self.depth), marker, self.name())
+    }
+}
+
+/// Listing of a directory where subdirectories are only read once expanded.
+#[derive(Debug, Default)]
+pub struct Directory {
+    expanded: BTreeSet<PathBuf>,
+    entries: Vec<Entry>,
+}
+
+impl Directory {
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn expand(&mut self, path: impl Into<PathBuf>) {
+        self.expanded.insert(path.into());
+    }
+
+    pub fn collapse(&mut self, path: &Path) {
+        self.expanded.retain(|expanded| !expanded.starts_with(path));
+    }
+
+    /// Reads `root` and every expanded directory beneath it, returning each
+    /// failure encountered along the way rather than stopping at the first.
+    pub fn read(&mut self, root: &Path) -> Vec<Error> {
+        let mut entries = vec![];
+        let mut errors = vec![];
+
+        self.read_into(root, 0, &mut entries, &mut errors);
+
+        self.entries = entries;
+
+        errors
+    }
+
+    fn read_into(
+        &self,
+        directory: &Path,
+        depth: usize,
+        entries: &mut Vec<Entry>,
+        errors: &mut Vec<Error>,
+    ) {
+        let read = match read_dir(directory) {
+            Ok(read) => read,
+            Err(error) => {
+                errors.push(Error::Io(directory.into(), error));
+                return;
+            }
+        };
+
+        let mut children = vec![];
+
+        for entry in read {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(error) => {
+                    errors.push(Error::Io(directory.into(), error));
+                    continue;
+                }
+            };
+
+            match symlink_metadata(&path) {
+                Ok(metadata) => children.push(Entry {
+                    kind: Kind::from(&metadata),
+                    size: metadata.len(),
+                    modified: metadata.modified().ok(),
+                    expanded: self.expanded.contains(&path),
+                    depth,
+                    path,
+                }),
+                Err(error) => errors.push(Error::Io(path, error)),
+            }
+        }
+
+        children.sort_by_key(|child| (child.kind, child.name()));
+
+        for child in children {
+            let path = child.path.clone();
+            let expanded = child.expanded && child.kind == Kind::Directory;
+
+            entries.push(child);
+
+            if expanded {
+                self.read_into(&path, depth + 1, entries, errors);
+            }
+        }
+    }
+}
+
+impl Display for Directory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .entries
+            .iter()
+            .map(|entry| entry.label().chars().count())
+            .max()
+            .unwrap_or(0);
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+
+            let label = entry.label();
+            let padding = width - label.chars().count();
+
+            let modified = entry
+                .modified
+                .map(DateTime::<Local>::from)
+                .map(|modified| modified.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+
+            write!(
+                f,
+                "{}{}  {:<9}  {:>6}  {}",
+                label,
+                " ".repeat(padding),
+                entry.kind,
+                size(entry.size),
+                modified,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn size(bytes: u64) -> String {
+    let units = ["B", "K", "M", "G", "T"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{}{}", bytes, units[unit])
+    } else {
+        format!("{:.1}{}", value, units[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::fs};
+
+    fn listed(directory: &Directory) -> Vec<(usize, String, Kind)> {
+        directory
+            .entries()
+            .iter()
+            .map(|entry| (entry.depth, entry.name(), entry.kind))
+            .collect()
+    }
+
+    #[test]
+    fn reading_again_picks_up_changes_and_expanded_directories() {
+        let root = std::env::temp_dir().join(format!("jago-directory-{}", std::process::id()));
+        let nested = root.join("nested");
+
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.join("top"), "top").unwrap();
+        fs::write(nested.join("inner"), "inner").unwrap();
+
+        let mut directory = Directory::default();
+
+        assert!(directory.read(&root).is_empty());
+        assert_eq!(
+            listed(&directory),
+            [
+                (0, "nested".into(), Kind::Directory),
+                (0, "top".into(), Kind::File),
+            ]
+        );
+
+        directory.expand(&nested);
+        fs::write(root.join("added"), "added").unwrap();
+        directory.read(&root);
+
+        assert_eq!(
+            listed(&directory),
+            [
+                (0, "nested".into(), Kind::Directory),
+                (1, "inner".into(), Kind::File),
+                (0, "added".into(), Kind::File),
+                (0, "top".into(), Kind::File),
+            ]
+        );
+        assert!(directory
+            .to_string()
+            .lines()
+            .next()
+            .unwrap()
+            .starts_with("▾ nested"));
+
+        directory.collapse(&nested);
+        fs::remove_file(root.join("top")).unwrap();
+        directory.read(&root);
+
+        assert_eq!(
+            listed(&directory),
+            [
+                (0, "nested".into(), Kind::Directory),
+                (0, "added".into(), Kind::File),
+            ]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn failures_are_returned_and_the_listing_emptied() {
+        let root = std::env::temp_dir().join(format!("jago-missing-{}", std::process::id()));
+        let mut directory = Directory::default();
+
+        let errors = directory.read(&root);
+
+        assert!(matches!(&errors[..], [Error::Io(path, _)] if path == &root));
+        assert!(directory.entries().is_empty());
+    }
+
+    #[test]
+    fn sizes_are_given_in_the_largest_whole_unit() {
+        assert_eq!(size(512), "512B");
+        assert_eq!(size(1536), "1.5K");
+        assert_eq!(size(3 * 1024 * 1024), "3.0M");
+    }
+}
@@ -0,0 +1,532 @@
+use crate::{
+    resolve, store::Store, write_atomic, Actor, Buffer, Change, Direction, Error, Format, Op, Query,
+};
+
+use ::{
+    instrument::prelude::*,
+    serde::{Deserialize, Serialize},
+    std::{
+        fmt::{self, Display},
+        fs,
+        io::ErrorKind,
+        ops::Range,
+        path::{Path, PathBuf},
+        sync::Mutex,
+    },
+    tokio::sync::broadcast::{self, error::TryRecvError},
+};
+
+/// Names an open buffer. There is one scratch, listing and log buffer at
+/// most, while files are known by their path.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Name {
+    Scratch,
+    Listing,
+    Log,
+    File(PathBuf),
+}
+
+impl Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Name::Scratch => f.pad("scratch"),
+            Name::Listing => f.pad("listing"),
+            Name::Log => f.pad("log"),
+            Name::File(path) => f.pad(&path.display().to_string()),
+        }
+    }
+}
+
+/// The matches of a query in the text as of a revision of it.
+type Matched = (usize, Query, Vec<Range<usize>>);
+
+/// A buffer along with what is known about it: the file it is saved to, if
+/// any, whether it has changed since, and where its history is kept.
+pub struct Document {
+    buffer: Buffer,
+    path: Option<PathBuf>,
+    dirty: bool,
+    format: Format,
+    store: Option<Store>,
+    conflicted: bool,
+    search: Option<Query>,
+    matched: Mutex<Option<Matched>>,
+    changes: broadcast::Receiver<Change>,
+}
+
+impl Document {
+    pub(crate) fn new(buffer: Buffer, store: Option<Store>) -> Self {
+        Self {
+            changes: buffer.subscribe(),
+            buffer,
+            path: None,
+            dirty: false,
+            format: Format::default(),
+            store,
+            conflicted: false,
+            search: None,
+            matched: Mutex::new(None),
+        }
+    }
+
+    /// What changed in the buffer since the last call, with a `Reset` in
+    /// place of any run of changes that were missed.
+    pub(crate) fn changes(&mut self) -> Vec<Change> {
+        let mut changes = vec![];
+
+        loop {
+            match self.changes.try_recv() {
+                Ok(change) => changes.push(change),
+                Err(TryRecvError::Lagged(_)) => changes.push(Change::Reset),
+                Err(TryRecvError::Empty | TryRecvError::Closed) => return changes,
+            }
+        }
+    }
+
+    /// Opens `path` on top of whatever history it has on disk, bringing the
+    /// buffer in line with the file if it changed outside of a session.
+    pub(crate) fn open(path: PathBuf, actor: Actor) -> Result<Self, Error> {
+        let content = String::from_utf8(fs::read(&path)?)?;
+
+        let mut store = Store::open(&Store::name(&path))?;
+
+        let mut document = Self::new(store.load()?, Some(store));
+
+        if document.buffer.to_string() != content {
+            document.load(&content, actor)?;
+        }
+
+        document.path = Some(path);
+
+        Ok(document)
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Whether the file changed on disk while there were unsaved edits,
+    /// leaving it to the user to `Reload` or `Save` over it.
+    pub fn conflicted(&self) -> bool {
+        self.conflicted
+    }
+
+    /// The query being searched for, from `Search` until `EndSearch`.
+    pub fn search(&self) -> Option<&Query> {
+        self.search.as_ref()
+    }
+
+    /// Where the current search matches, for frontends to highlight. They
+    /// are searched for again only once the query or the text changes.
+    pub fn matches(&self) -> Vec<Range<usize>> {
+        let query = match &self.search {
+            Some(query) => query,
+            None => return vec![],
+        };
+
+        let revision = self.buffer.revision();
+
+        let mut matched = match self.matched.lock() {
+            Ok(matched) => matched,
+            Err(_) => return self.buffer.search(query).unwrap_or_default(),
+        };
+
+        if let Some((at, of, matches)) = &*matched {
+            if *at == revision && of == query {
+                return matches.clone();
+            }
+        }
+
+        let matches = self.buffer.search(query).unwrap_or_default();
+
+        *matched = Some((revision, query.clone(), matches.clone()));
+
+        matches
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub(crate) fn set_format(&mut self, format: Format) {
+        self.format = format;
+    }
+}
+
+impl Document {
+    pub(crate) fn validate(&self, op: &Op) -> Result<(), Error> {
+        match op {
+            Op::Move(_) | Op::Extend(_) | Op::Deselect => Ok(()),
+            Op::SelectNext | Op::SplitLines => Ok(()),
+            Op::Mark(_) | Op::Unmark(_) | Op::Jump(_) => Ok(()),
+            Op::Type(_) | Op::Paste(_) | Op::Backspace | Op::DeleteForward => Ok(()),
+            Op::Undo | Op::Redo => Ok(()),
+            Op::EndSearch => Ok(()),
+            Op::RemoveAnnotation(_) | Op::ToggleStyle(_) => Ok(()),
+            Op::Search(query) => query
+                .compile()
+                .map(|_| ())
+                .map_err(|error| Error::Buffer(error.into())),
+            Op::FindNext | Op::FindPrevious | Op::ReplaceNext(_) | Op::ReplaceAll(_) => {
+                self.search.as_ref().map(|_| ()).ok_or(Error::NoSearch)
+            }
+            Op::Save | Op::Reload => self.path.as_ref().map(|_| ()).ok_or(Error::NoPath),
+            Op::Insert(index, _) => self.buffer.check(&(*index..*index)).map_err(Error::from),
+            Op::Delete(range) | Op::Replace(range, _) | Op::Select(range) => {
+                self.buffer.check(range).map_err(Error::from)
+            }
+            Op::Annotate(range, _) | Op::Unstyle(range, _) => {
+                self.buffer.check(range).map_err(Error::from)
+            }
+            Op::Read | Op::Expand(_) | Op::Collapse(_) | Op::Open(_) | Op::SaveAs(_) => {
+                Err(Error::Unaddressable)
+            }
+            Op::Refresh | Op::Switch(_) | Op::Close(_) | Op::To(..) | Op::Log(_) => {
+                Err(Error::Unaddressable)
+            }
+        }
+    }
+
+    pub(crate) fn apply(&mut self, op: Op, actor: Actor) -> Result<(), Error> {
+        match op {
+            Op::Insert(index, text) => self.insert(index, &text, actor),
+            Op::Delete(range) => self.delete(range, actor),
+            Op::Replace(range, text) => self.replace(range, &text, actor),
+            Op::Move(graphemes) => self.buffer.step(graphemes, actor).map_err(Error::from),
+            Op::Extend(graphemes) => self.buffer.extend(graphemes, actor).map_err(Error::from),
+            Op::Select(range) => self.buffer.select(range, actor).map_err(Error::from),
+            Op::Deselect => self.buffer.collapse(actor).map_err(Error::from),
+            Op::SelectNext => self.buffer.select_next(actor).map_err(Error::from),
+            Op::SplitLines => self.buffer.split_lines(actor).map_err(Error::from),
+            Op::Mark(name) => self.mark(&name, actor),
+            Op::Unmark(name) => self.buffer.remove_mark(&name).map_err(Error::from),
+            Op::Jump(name) => self.buffer.jump(&name, actor).map_err(Error::from),
+            Op::Type(text) => self.write(&text, actor),
+            Op::Paste(text) => self.paste(&text, actor),
+            Op::Backspace => self.backspace(actor),
+            Op::DeleteForward => self.delete_forward(actor),
+            Op::Save => self.save(),
+            Op::Undo => self.undo(actor),
+            Op::Redo => self.redo(actor),
+            Op::Reload => self.reload(actor),
+            Op::Search(query) => self.find(query, actor),
+            Op::FindNext => self.find_next(Direction::Forward, actor),
+            Op::FindPrevious => self.find_next(Direction::Backward, actor),
+            Op::ReplaceNext(replacement) => self.replace_next(&replacement, actor),
+            Op::ReplaceAll(replacement) => self.replace_all(&replacement, actor),
+            Op::EndSearch => {
+                self.search = None;
+                Ok(())
+            }
+            Op::Annotate(range, style) => self
+                .buffer
+                .annotate(range, style, actor)
+                .map(|_| ())
+                .map_err(Error::from),
+            Op::Unstyle(range, style) => self
+                .buffer
+                .unstyle(range, &style, actor)
+                .map_err(Error::from),
+            Op::RemoveAnnotation(tag) => self.buffer.remove_annotation(tag).map_err(Error::from),
+            Op::ToggleStyle(style) => self.buffer.toggle_style(style, actor).map_err(Error::from),
+            Op::Read | Op::Expand(_) | Op::Collapse(_) | Op::Open(_) | Op::SaveAs(_) => {
+                Err(Error::Unaddressable)
+            }
+            Op::Refresh | Op::Switch(_) | Op::Close(_) | Op::To(..) | Op::Log(_) => {
+                Err(Error::Unaddressable)
+            }
+        }
+    }
+
+    fn insert(&mut self, index: usize, text: &str, actor: Actor) -> Result<(), Error> {
+        self.buffer
+            .transact(actor, |buffer| buffer.insert(index, text, actor))?;
+
+        self.dirty = true;
+
+        Ok(())
+    }
+
+    fn delete(&mut self, range: Range<usize>, actor: Actor) -> Result<(), Error> {
+        self.buffer
+            .transact(actor, |buffer| buffer.delete(range, actor))?;
+
+        self.dirty = true;
+
+        Ok(())
+    }
+
+    fn replace(&mut self, range: Range<usize>, text: &str, actor: Actor) -> Result<(), Error> {
+        self.buffer
+            .transact(actor, |buffer| buffer.replace(range, text, actor))?;
+
+        self.dirty = true;
+
+        Ok(())
+    }
+
+    fn mark(&mut self, name: &str, actor: Actor) -> Result<(), Error> {
+        let cursor = self.buffer.cursor(actor)?;
+
+        self.buffer
+            .set_mark(name, cursor, actor)
+            .map_err(Error::from)
+    }
+
+    fn write(&mut self, text: &str, actor: Actor) -> Result<(), Error> {
+        self.buffer.write(text, actor)?;
+        self.dirty = true;
+
+        Ok(())
+    }
+
+    fn paste(&mut self, text: &str, actor: Actor) -> Result<(), Error> {
+        self.buffer.paste(text, actor)?;
+        self.dirty = true;
+
+        Ok(())
+    }
+
+    fn backspace(&mut self, actor: Actor) -> Result<(), Error> {
+        self.buffer.backspace(actor)?;
+        self.dirty = true;
+
+        Ok(())
+    }
+
+    fn delete_forward(&mut self, actor: Actor) -> Result<(), Error> {
+        self.buffer.delete_forward(actor)?;
+        self.dirty = true;
+
+        Ok(())
+    }
+
+    fn undo(&mut self, actor: Actor) -> Result<(), Error> {
+        if self.buffer.undo(actor)? {
+            self.dirty = true;
+        }
+
+        Ok(())
+    }
+
+    fn redo(&mut self, actor: Actor) -> Result<(), Error> {
+        if self.buffer.redo(actor)? {
+            self.dirty = true;
+        }
+
+        Ok(())
+    }
+
+    /// Selects the first match from the start of the selection on, so that
+    /// typing more of the query keeps to the same match where it can.
+    fn find(&mut self, query: Query, actor: Actor) -> Result<(), Error> {
+        let from = self.buffer.selection(actor)?.start();
+
+        if let Some(range) = self.buffer.find(&query, from, Direction::Forward)? {
+            self.buffer.select(range, actor)?;
+        }
+
+        self.search = Some(query);
+
+        Ok(())
+    }
+
+    fn find_next(&mut self, direction: Direction, actor: Actor) -> Result<(), Error> {
+        let query = self.search.as_ref().ok_or(Error::NoSearch)?;
+
+        self.buffer.find_next(query, direction, actor)?;
+
+        Ok(())
+    }
+
+    fn replace_next(&mut self, replacement: &str, actor: Actor) -> Result<(), Error> {
+        let query = self.search.as_ref().ok_or(Error::NoSearch)?;
+
+        if self.buffer.replace_next(query, replacement, actor)? {
+            self.dirty = true;
+        }
+
+        Ok(())
+    }
+
+    fn replace_all(&mut self, replacement: &str, actor: Actor) -> Result<(), Error> {
+        let query = self.search.as_ref().ok_or(Error::NoSearch)?;
+
+        if self.buffer.replace_all(query, replacement, actor)? > 0 {
+            self.dirty = true;
+        }
+
+        Ok(())
+    }
+
+    /// Brings in the file as it now is on disk unless there are unsaved
+    /// edits, in which case it is flagged as conflicted instead.
+    pub(crate) fn merge(&mut self, actor: Actor) -> Result<(), Error> {
+        let path = self.path.clone().ok_or(Error::NoPath)?;
+
+        let content = match fs::read(&path) {
+            Ok(bytes) => String::from_utf8(bytes)?,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                warn!("{} was removed", path.display());
+
+                return Ok(());
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        if content == self.buffer.to_string() {
+            self.conflicted = false;
+
+            return Ok(());
+        }
+
+        if self.dirty {
+            warn!("{} changed on disk", path.display());
+
+            self.conflicted = true;
+
+            return Ok(());
+        }
+
+        info!("merging {} from disk", path.display());
+
+        // Not an edit of the user's own, so undo leaves it alone.
+        self.buffer.adopt(&content, actor)?;
+
+        Ok(())
+    }
+
+    /// Takes the file as it is on disk, dropping unsaved edits.
+    fn reload(&mut self, actor: Actor) -> Result<(), Error> {
+        let path = self.path.clone().ok_or(Error::NoPath)?;
+
+        let content = String::from_utf8(fs::read(&path)?)?;
+
+        self.buffer.import(&content, actor)?;
+
+        self.dirty = false;
+        self.conflicted = false;
+
+        Ok(())
+    }
+
+    pub(crate) fn persist(&mut self) -> Result<(), Error> {
+        let ops = self.buffer.drain()?;
+
+        if let Some(store) = &mut self.store {
+            store.append(&ops, &self.buffer)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn save_as(&mut self, path: PathBuf) -> Result<(), Error> {
+        self.path = Some(path);
+
+        self.save()
+    }
+
+    fn save(&mut self) -> Result<(), Error> {
+        let path = resolve(self.path.take().ok_or(Error::NoPath)?)?;
+
+        let result = write_atomic(&path, self.buffer.to_string());
+
+        self.path = Some(path);
+
+        result?;
+
+        if let Some(store) = &mut self.store {
+            store.sync()?;
+        }
+
+        self.dirty = false;
+        self.conflicted = false;
+
+        Ok(())
+    }
+
+    /// Replaces the text wholesale, as something generated rather than
+    /// edited, so there is nothing to undo or save. The path stays, so a
+    /// listing or log saved somewhere is saved there again.
+    pub(crate) fn load(&mut self, content: &str, actor: Actor) -> Result<(), Error> {
+        self.buffer.import(content, actor)?;
+        self.buffer.forget()?;
+
+        self.dirty = false;
+
+        Ok(())
+    }
+
+    /// Replaces the text wholesale as a single edit, which can be undone and
+    /// leaves the document dirty if it changed anything.
+    pub(crate) fn rewrite(&mut self, content: &str, actor: Actor) -> Result<(), Error> {
+        if self.buffer.to_string() == content {
+            return Ok(());
+        }
+
+        self.buffer
+            .transact(actor, |buffer| buffer.import(content, actor))?;
+
+        self.dirty = true;
+
+        Ok(())
+    }
+
+    /// Adds generated text at the end, outside of any transaction, so like
+    /// `load` it leaves nothing to undo.
+    pub(crate) fn append(&mut self, text: &str, actor: Actor) -> Result<(), Error> {
+        let end = self.buffer.len()?;
+
+        self.buffer.insert(end, text, actor)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_edits_that_succeed_make_a_document_dirty() {
+        let mut document = Document::new(Buffer::new(), None);
+
+        assert!(document.apply(Op::Insert(1, "x".into()), 0).is_err());
+        assert!(document.apply(Op::Delete(0..1), 0).is_err());
+        assert!(document.apply(Op::Replace(0..1, "x".into()), 0).is_err());
+        assert!(!document.dirty());
+
+        document.apply(Op::Insert(0, "x".into()), 0).unwrap();
+
+        assert!(document.dirty());
+    }
+
+    #[test]
+    fn undo_leaves_changes_merged_from_disk_alone() {
+        let path = std::env::temp_dir().join(format!("jago-merge-{}", std::process::id()));
+        let mut document = Document::new(Buffer::new(), None);
+
+        document.apply(Op::Insert(0, "mine\n".into()), 0).unwrap();
+        document.save_as(path.clone()).unwrap();
+
+        fs::write(&path, "mine\ntheirs\n").unwrap();
+        document.merge(0).unwrap();
+
+        assert_eq!(document.buffer().to_string(), "mine\ntheirs\n");
+        assert!(!document.dirty());
+
+        document.apply(Op::Undo, 0).unwrap();
+
+        assert_eq!(document.buffer().to_string(), "theirs\n");
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,1206 @@
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Magic")]
+    Magic,
+    #[error("Version {0}")]
+    Version(Version),
+    #[error("Truncated")]
+    Truncated,
+    #[error("Trailing {0}")]
+    Trailing(usize),
+    #[error("Varint")]
+    Varint,
+    #[error("Length")]
+    Length,
+    #[error("Unsupported {0}")]
+    Unsupported(&'static str),
+    #[error("Message {0}")]
+    Message(String),
+}
+
+use ::{
+    serde::{
+        de::{
+            self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess,
+            SeqAccess, VariantAccess, Visitor,
+        },
+        ser::{self, Serialize},
+    },
+    std::fmt::{self, Display},
+};
+
+/// Leads every encoded document and log so foreign bytes are refused early.
+static MAGIC: &[u8; 4] = b"jago";
+
+/// Bumped when old readers can no longer make sense of what is written,
+/// such as a variant added to an enum, whose tag they wouldn't know.
+pub static MAJOR: u8 = 2;
+
+/// Bumped when fields are appended to a struct, which old readers can
+/// safely skip. Starts over from zero with each major version.
+pub static MINOR: u8 = 0;
+
+/// Schema version carried in the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl Version {
+    pub fn current() -> Self {
+        Self {
+            major: MAJOR,
+            minor: MINOR,
+        }
+    }
+
+    /// A newer minor version may append to what this one writes, so bytes
+    /// left over after a value are only an error up to our own version.
+    fn newer(&self) -> bool {
+        self.minor > MINOR
+    }
+
+    /// An older minor version may have written structs without the fields
+    /// since appended to them.
+    fn older(&self) -> bool {
+        self.minor < MINOR
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+pub fn header(bytes: &mut Vec<u8>) {
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(MAJOR);
+    bytes.push(MINOR);
+}
+
+/// Checks the header and returns the version along with what follows it.
+pub fn version(bytes: &[u8]) -> Result<(Version, &[u8]), Error> {
+    let rest = bytes.strip_prefix(&MAGIC[..]).ok_or(Error::Magic)?;
+
+    let (version, rest) = match rest {
+        [major, minor, rest @ ..] => (
+            Version {
+                major: *major,
+                minor: *minor,
+            },
+            rest,
+        ),
+        _ => return Err(Error::Truncated),
+    };
+
+    if version.major != MAJOR {
+        return Err(Error::Version(version));
+    }
+
+    Ok((version, rest))
+}
+
+/// Encodes `value` as a standalone document, header included.
+pub fn encode<T: ?Sized + Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+    let mut bytes = vec![];
+
+    header(&mut bytes);
+    value.serialize(&mut Encoder(&mut bytes))?;
+
+    Ok(bytes)
+}
+
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+    let (version, body) = version(bytes)?;
+
+    read(body, version)
+}
+
+/// Appends `value` prefixed with its length, for streams of values sharing
+/// one header.
+pub fn frame<T: ?Sized + Serialize>(value: &T, bytes: &mut Vec<u8>) -> Result<(), Error> {
+    let mut body = vec![];
+
+    value.serialize(&mut Encoder(&mut body))?;
+
+    varint(body.len() as u64, bytes);
+    bytes.extend_from_slice(&body);
+
+    Ok(())
+}
+
+/// Reads the frame at the front of `bytes` and advances past it.
+pub fn unframe<T: DeserializeOwned>(bytes: &mut &[u8], version: Version) -> Result<T, Error> {
+    read(split_frame(bytes)?, version)
+}
+
+/// Takes the body of the frame at the front of `bytes` without decoding it
+/// and advances past it, so a body that fails to decode can be skipped. A
+/// frame cut short is `Truncated`, and `bytes` is then left as it was.
+pub fn split_frame<'a>(bytes: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    let mut decoder = Decoder {
+        bytes,
+        older: false,
+    };
+
+    let length = decoder.varint()? as usize;
+    let body = decoder.take(length)?;
+
+    *bytes = decoder.bytes;
+
+    Ok(body)
+}
+
+/// Decodes a body written under `version`, such as a frame's.
+pub fn read<T: DeserializeOwned>(bytes: &[u8], version: Version) -> Result<T, Error> {
+    let mut decoder = Decoder {
+        bytes,
+        older: version.older(),
+    };
+
+    let value = T::deserialize(&mut decoder)?;
+
+    match decoder.bytes.len() {
+        0 => Ok(value),
+        _ if version.newer() => Ok(value),
+        trailing => Err(Error::Trailing(trailing)),
+    }
+}
+
+/// LEB128, so the small counters and lengths that make up most ops take a
+/// byte each.
+fn varint(mut value: u64, bytes: &mut Vec<u8>) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    bytes.push(value as u8);
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+/// Writes values without any field names or type tags: structs are their
+/// fields in order, enums a variant index then its fields, and sequences and
+/// maps a length then their items.
+struct Encoder<'a>(&'a mut Vec<u8>);
+
+impl<'a> Encoder<'a> {
+    fn length(&mut self, length: Option<usize>) -> Result<(), Error> {
+        varint(length.ok_or(Error::Length)? as u64, self.0);
+
+        Ok(())
+    }
+}
+
+impl<'a, 'b> ser::Serializer for &'b mut Encoder<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.0.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.0.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        varint(zigzag(v), self.0);
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.0.push(v);
+        Ok(())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        varint(v, self.0);
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        varint(v.len() as u64, self.0);
+        self.0.extend_from_slice(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.0.push(0);
+        Ok(())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), Error> {
+        self.0.push(1);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        index: u32,
+        _: &'static str,
+    ) -> Result<(), Error> {
+        self.serialize_u32(index)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        index: u32,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.serialize_u32(index)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, length: Option<usize>) -> Result<Self, Error> {
+        self.length(length)?;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        index: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self, Error> {
+        self.serialize_u32(index)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, length: Option<usize>) -> Result<Self, Error> {
+        self.length(length)?;
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        index: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self, Error> {
+        self.serialize_u32(index)?;
+        Ok(self)
+    }
+}
+
+impl<'a, 'b> ser::SerializeSeq for &'b mut Encoder<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b> ser::SerializeTuple for &'b mut Encoder<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b> ser::SerializeTupleStruct for &'b mut Encoder<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b> ser::SerializeTupleVariant for &'b mut Encoder<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b> ser::SerializeMap for &'b mut Encoder<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b> ser::SerializeStruct for &'b mut Encoder<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b> ser::SerializeStructVariant for &'b mut Encoder<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Reads back what `Encoder` wrote. Nothing in the bytes says what they
+/// hold, so it relies on the type being decoded to ask for the right thing.
+struct Decoder<'de> {
+    bytes: &'de [u8],
+    older: bool,
+}
+
+impl<'de> Decoder<'de> {
+    fn take(&mut self, length: usize) -> Result<&'de [u8], Error> {
+        if self.bytes.len() < length {
+            return Err(Error::Truncated);
+        }
+
+        let (taken, rest) = self.bytes.split_at(length);
+
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, Error> {
+        let mut value = 0;
+
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+
+            value |= ((byte & 0x7f) as u64) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(Error::Varint)
+    }
+
+    fn signed(&mut self) -> Result<i64, Error> {
+        self.varint().map(unzigzag)
+    }
+
+    fn length(&mut self) -> Result<usize, Error> {
+        let length = self.varint()? as usize;
+
+        // Every item takes at least a byte, which stops a corrupt length from
+        // reserving more than the input could ever hold.
+        match length <= self.bytes.len() {
+            true => Ok(length),
+            false => Err(Error::Truncated),
+        }
+    }
+
+    fn narrow<T: TryFrom<u64>>(value: u64) -> Result<T, Error> {
+        T::try_from(value).map_err(|_| Error::Varint)
+    }
+
+    fn narrow_signed<T: TryFrom<i64>>(value: i64) -> Result<T, Error> {
+        T::try_from(value).map_err(|_| Error::Varint)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut array = [0; N];
+
+        array.copy_from_slice(self.take(N)?);
+
+        Ok(array)
+    }
+
+    fn str(&mut self) -> Result<&'de str, Error> {
+        let length = self.length()?;
+
+        std::str::from_utf8(self.take(length)?).map_err(|error| Error::Message(error.to_string()))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Decoder<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Error> {
+        Err(Error::Unsupported("any"))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Error> {
+        Err(Error::Unsupported("ignored any"))
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Error> {
+        Err(Error::Unsupported("identifier"))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.byte()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            byte => Err(Error::Message(format!("invalid bool {}", byte))),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i8(self.byte()? as i8)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i16(Decoder::narrow_signed(self.signed()?)?)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i32(Decoder::narrow_signed(self.signed()?)?)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i64(self.signed()?)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u8(self.byte()?)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u16(Decoder::narrow(self.varint()?)?)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u32(Decoder::narrow(self.varint()?)?)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u64(self.varint()?)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_f32(f32::from_le_bytes(self.array()?))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_f64(f64::from_le_bytes(self.array()?))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let code = Decoder::narrow(self.varint()?)?;
+
+        match char::from_u32(code) {
+            Some(c) => visitor.visit_char(c),
+            None => Err(Error::Message(format!("invalid char {:x}", code))),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_borrowed_str(self.str()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let length = self.length()?;
+
+        visitor.visit_borrowed_bytes(self.take(length)?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.byte()? {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            byte => Err(Error::Message(format!("invalid option {}", byte))),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let length = self.length()?;
+
+        visitor.visit_seq(Items {
+            decoder: self,
+            remaining: length,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        length: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_seq(Items {
+            decoder: self,
+            remaining: length,
+        })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        length: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_tuple(length, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let length = self.length()?;
+
+        visitor.visit_map(Items {
+            decoder: self,
+            remaining: length,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_seq(Fields {
+            decoder: self,
+            remaining: fields.len(),
+        })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_enum(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+struct Items<'a, 'de> {
+    decoder: &'a mut Decoder<'de>,
+    remaining: usize,
+}
+
+impl<'a, 'de> SeqAccess<'de> for Items<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        self.remaining -= 1;
+
+        seed.deserialize(&mut *self.decoder).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+/// A struct's fields, which may stop short when the struct was the last thing
+/// written by an older minor version and fields were since appended to it.
+/// Those are left to their `#[serde(default)]`, while anything written by
+/// this version has to have them all.
+struct Fields<'a, 'de> {
+    decoder: &'a mut Decoder<'de>,
+    remaining: usize,
+}
+
+impl<'a, 'de> SeqAccess<'de> for Fields<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if self.remaining == 0 || (self.decoder.older && self.decoder.bytes.is_empty()) {
+            return Ok(None);
+        }
+
+        self.remaining -= 1;
+
+        seed.deserialize(&mut *self.decoder).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'a, 'de> MapAccess<'de> for Items<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        self.remaining -= 1;
+
+        seed.deserialize(&mut *self.decoder).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(&mut *self.decoder)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'de> EnumAccess<'de> for &mut Decoder<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let index: u32 = Decoder::narrow(self.varint()?)?;
+
+        let variant = seed.deserialize(index.into_deserializer())?;
+
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for &mut Decoder<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, length: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_tuple(self, length, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffer::{Op, Snapshot},
+        Buffer, CmRDT, Style,
+    };
+
+    use ::{
+        crdts::{list, map},
+        serde::{Deserialize, Serialize},
+        std::collections::BTreeMap,
+    };
+
+    /// Ops of every kind a buffer makes, from more than one actor.
+    fn ops() -> (Buffer, Vec<Op>) {
+        let mut buffer = Buffer::new();
+
+        buffer.insert(0, "héllo wörld\n", 0).unwrap();
+        buffer.insert(3, "✓", 1).unwrap();
+        buffer.delete(0..2, 1).unwrap();
+        buffer.select(1..4, 0).unwrap();
+        buffer.select(2..2, 1).unwrap();
+        buffer.set_mark("here", 5, 0).unwrap();
+        buffer.set_mark("gone", 1, 1).unwrap();
+        buffer.remove_mark("gone").unwrap();
+
+        let tag = buffer.annotate(0..3, Style::Bold, 0).unwrap();
+        buffer
+            .annotate(2..6, Style::Link("https://example.com".into()), 1)
+            .unwrap();
+        buffer
+            .annotate(4..7, Style::Diagnostic("typo".into()), 0)
+            .unwrap();
+        buffer.remove_annotation(tag).unwrap();
+
+        let ops = buffer.drain().unwrap();
+
+        (buffer, ops)
+    }
+
+    #[test]
+    fn ops_round_trip() {
+        let (_, ops) = ops();
+
+        let kinds = [
+            ops.iter()
+                .any(|op| matches!(op, Op::List(list::Op::Insert { .. }))),
+            ops.iter()
+                .any(|op| matches!(op, Op::List(list::Op::Delete { .. }))),
+            ops.iter().any(|op| matches!(op, Op::Cursors(_))),
+            ops.iter()
+                .any(|op| matches!(op, Op::Marks(map::Op::Up { .. }))),
+            ops.iter()
+                .any(|op| matches!(op, Op::Marks(map::Op::Rm { .. }))),
+            ops.iter()
+                .any(|op| matches!(op, Op::Styles(map::Op::Up { .. }))),
+            ops.iter()
+                .any(|op| matches!(op, Op::Styles(map::Op::Rm { .. }))),
+        ];
+
+        assert!(kinds.iter().all(|kind| *kind));
+
+        for op in &ops {
+            assert_eq!(decode::<Op>(&encode(op).unwrap()).unwrap(), *op);
+        }
+
+        let mut stream = vec![];
+
+        header(&mut stream);
+
+        for op in &ops {
+            frame(op, &mut stream).unwrap();
+        }
+
+        let (version, mut rest) = version(&stream).unwrap();
+
+        for op in &ops {
+            assert_eq!(unframe::<Op>(&mut rest, version).unwrap(), *op);
+        }
+
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn ops_replay_into_the_same_buffer() {
+        let (buffer, ops) = ops();
+        let mut replica = Buffer::new();
+
+        // Applied without validating, as a store replays them, since a map
+        // won't validate an update to a new key by an actor that has updated
+        // others.
+        for op in ops {
+            let op = decode::<Op>(&encode(&op).unwrap()).unwrap();
+
+            replica.apply(op);
+        }
+
+        assert_eq!(replica.to_string(), buffer.to_string());
+        assert_eq!(replica.marks().unwrap(), buffer.marks().unwrap());
+        assert_eq!(
+            replica.annotations().unwrap(),
+            buffer.annotations().unwrap()
+        );
+        assert_eq!(
+            replica.selections(0).unwrap(),
+            buffer.selections(0).unwrap()
+        );
+        assert_eq!(
+            replica.selections(1).unwrap(),
+            buffer.selections(1).unwrap()
+        );
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        let (buffer, _) = ops();
+
+        let snapshot = decode::<Snapshot>(&encode(&buffer.snapshot().unwrap()).unwrap()).unwrap();
+        let mut restored = Buffer::from(snapshot);
+
+        assert_eq!(restored.to_string(), buffer.to_string());
+        assert_eq!(restored.marks().unwrap(), buffer.marks().unwrap());
+        assert_eq!(
+            restored.annotations().unwrap(),
+            buffer.annotations().unwrap()
+        );
+        assert_eq!(
+            restored.selections(0).unwrap(),
+            buffer.selections(0).unwrap()
+        );
+
+        // The restored clock has to be far enough along that new ops are
+        // accepted by the original.
+        restored.insert(0, "new", 0).unwrap();
+
+        let mut original = buffer;
+
+        for op in restored.drain().unwrap() {
+            original.validate_op(&op).unwrap();
+            original.apply(op);
+        }
+
+        assert_eq!(original.to_string(), restored.to_string());
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Scalars {
+        flag: bool,
+        small: i8,
+        negative: i64,
+        large: u64,
+        float: f64,
+        letter: char,
+        text: String,
+        bytes: Vec<u8>,
+        missing: Option<u32>,
+        present: Option<u32>,
+        map: BTreeMap<String, i32>,
+        tuple: (u16, i32),
+        unit: (),
+    }
+
+    #[test]
+    fn scalars_round_trip() {
+        let scalars = Scalars {
+            flag: true,
+            small: -128,
+            negative: i64::MIN,
+            large: u64::MAX,
+            float: -1.5e300,
+            letter: '😀',
+            text: "tëxt".into(),
+            bytes: vec![0, 0x80, 0xff],
+            missing: None,
+            present: Some(u32::MAX),
+            map: BTreeMap::from([("a".into(), -1), ("b".into(), i32::MAX)]),
+            tuple: (u16::MAX, i32::MIN),
+            unit: (),
+        };
+
+        assert_eq!(
+            decode::<Scalars>(&encode(&scalars).unwrap()).unwrap(),
+            scalars
+        );
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Old {
+        number: u32,
+        text: String,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct New {
+        number: u32,
+        text: String,
+        #[serde(default)]
+        extra: Vec<u32>,
+    }
+
+    fn newer() -> Version {
+        Version {
+            major: MAJOR,
+            minor: MINOR + 1,
+        }
+    }
+
+    #[test]
+    fn newer_minor_versions_may_append_fields() {
+        let new = New {
+            number: 7,
+            text: "seven".into(),
+            extra: vec![1, 2, 3],
+        };
+
+        let mut bytes = encode(&new).unwrap();
+
+        assert!(matches!(
+            decode::<Old>(&bytes),
+            Err(Error::Trailing(length)) if length > 0
+        ));
+
+        bytes[MAGIC.len() + 1] = MINOR + 1;
+
+        assert_eq!(
+            decode::<Old>(&bytes).unwrap(),
+            Old {
+                number: 7,
+                text: "seven".into()
+            }
+        );
+
+        let mut stream = vec![];
+
+        frame(&new, &mut stream).unwrap();
+        frame(&new, &mut stream).unwrap();
+
+        let mut rest = &stream[..];
+
+        for _ in 0..2 {
+            assert_eq!(unframe::<Old>(&mut rest, newer()).unwrap().number, 7);
+        }
+
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn older_minor_versions_leave_appended_fields_to_their_default() {
+        let old = Old {
+            number: 1,
+            text: "one".into(),
+        };
+
+        let bytes = encode(&old).unwrap();
+
+        assert!(matches!(decode::<New>(&bytes), Err(Error::Truncated)));
+
+        // As `read` decodes a body written under an older minor version,
+        // which there is none of yet under this major one.
+        let (_, body) = version(&bytes).unwrap();
+
+        let mut decoder = Decoder {
+            bytes: body,
+            older: true,
+        };
+
+        assert_eq!(
+            New::deserialize(&mut decoder).unwrap(),
+            New {
+                number: 1,
+                text: "one".into(),
+                extra: vec![]
+            }
+        );
+    }
+
+    #[test]
+    fn other_major_versions_and_foreign_bytes_are_refused() {
+        let mut bytes = encode(&1u32).unwrap();
+
+        bytes[MAGIC.len()] = MAJOR + 1;
+
+        assert!(matches!(
+            decode::<u32>(&bytes),
+            Err(Error::Version(Version { major, .. })) if major == MAJOR + 1
+        ));
+        assert!(matches!(
+            decode::<u32>(b"gajo\x01\x00\x01"),
+            Err(Error::Magic)
+        ));
+        assert!(matches!(decode::<u32>(b""), Err(Error::Magic)));
+        assert!(matches!(decode::<u32>(b"jago\x01"), Err(Error::Truncated)));
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        let (buffer, ops) = ops();
+
+        let mut documents = ops.iter().map(|op| encode(op).unwrap()).collect::<Vec<_>>();
+
+        documents.push(encode(&buffer.snapshot().unwrap()).unwrap());
+
+        for bytes in &documents {
+            for length in 0..bytes.len() {
+                let cut = &bytes[..length];
+
+                match documents.last() == Some(bytes) {
+                    true => assert!(decode::<Snapshot>(cut).is_err()),
+                    false => assert!(decode::<Op>(cut).is_err()),
+                }
+            }
+        }
+
+        let mut stream = vec![];
+
+        frame(&ops[0], &mut stream).unwrap();
+
+        for length in 0..stream.len() {
+            let mut cut = &stream[..length];
+
+            assert!(unframe::<Op>(&mut cut, Version::current()).is_err());
+        }
+    }
+
+    #[test]
+    fn corrupt_lengths_are_an_error() {
+        let mut bytes = vec![];
+
+        header(&mut bytes);
+        bytes.extend_from_slice(&[0xff; 10]);
+
+        assert!(matches!(decode::<String>(&bytes), Err(Error::Varint)));
+
+        let mut bytes = vec![];
+
+        header(&mut bytes);
+        varint(u64::MAX >> 1, &mut bytes);
+
+        assert!(matches!(decode::<Vec<u8>>(&bytes), Err(Error::Truncated)));
+    }
+}
@@ -0,0 +1,98 @@
+mod yaml;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Buffer {0}")]
+    Buffer(#[from] buffer::Error),
+    #[error("Json {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("TomlSerialize {0}")]
+    TomlSerialize(#[from] toml::ser::Error),
+    #[error("TomlDeserialize {0}")]
+    TomlDeserialize(#[from] toml::de::Error),
+    #[error("Unknown {0}")]
+    Unknown(String),
+}
+
+use crate::buffer::{self, Buffer};
+
+use ::{
+    serde::{de::DeserializeOwned, Serialize},
+    std::{
+        fmt::{self, Display},
+        str::FromStr,
+    },
+};
+
+/// How a value is laid out as text when it is rendered into a buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Tree,
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    pub fn write<T: ?Sized + Serialize>(&self, value: &T) -> Result<String, Error> {
+        match self {
+            Format::Tree => {
+                let buffer = Buffer::new();
+
+                value.serialize(buffer.clone())?;
+
+                Ok(buffer.to_string())
+            }
+            Format::Json => {
+                let mut text = serde_json::to_string_pretty(value)?;
+
+                text.push('\n');
+
+                Ok(text)
+            }
+            Format::Toml => {
+                let table = toml::Value::try_from(value)?;
+
+                Ok(toml::to_string_pretty(&table)?)
+            }
+            Format::Yaml => Ok(yaml::write(&serde_json::to_value(value)?)),
+        }
+    }
+
+    pub fn read<T: DeserializeOwned>(&self, text: &str) -> Result<T, Error> {
+        match self {
+            Format::Tree => Ok(buffer::from_str(text)?),
+            Format::Json => Ok(serde_json::from_str(text)?),
+            Format::Toml => Ok(toml::from_str(text)?),
+            Format::Yaml => Ok(serde_json::from_value(yaml::read(text)?)?),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Format::Tree => "tree",
+            Format::Json => "json",
+            Format::Toml => "toml",
+            Format::Yaml => "yaml",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Error> {
+        match name {
+            "tree" => Ok(Format::Tree),
+            "json" => Ok(Format::Json),
+            "toml" => Ok(Format::Toml),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            _ => Err(Error::Unknown(name.into())),
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use crate::buffer::{
+    de::{parse, Node, Value as Tree},
+    Error,
+};
+
+use serde_json::{Map, Number, Value};
+
+#[derive(Clone, Copy)]
+enum Position {
+    Line,
+    Key,
+    Item,
+}
+
+/// Writes block style YAML using the same indentation as the tree layout,
+/// which lets `read` reuse the tree parser for the structure.
+pub fn write(value: &Value) -> String {
+    let mut text = String::new();
+
+    node(&mut text, value, 0, Position::Line);
+
+    text
+}
+
+fn node(text: &mut String, value: &Value, depth: usize, position: Position) {
+    match value {
+        Value::Array(items) if !items.is_empty() => {
+            for (index, item) in items.iter().enumerate() {
+                entry(text, index, depth, position);
+                text.push('-');
+                node(text, item, depth + 1, Position::Item);
+            }
+        }
+        Value::Object(entries) if !entries.is_empty() => {
+            for (index, (key, value)) in entries.iter().enumerate() {
+                entry(text, index, depth, position);
+                text.push_str(&string(key));
+                text.push(':');
+                node(text, value, depth + 1, Position::Key);
+            }
+        }
+        value => {
+            match position {
+                Position::Line => text.push_str(&"  ".repeat(depth)),
+                Position::Key | Position::Item => text.push(' '),
+            }
+
+            text.push_str(&scalar(value));
+            text.push('\n');
+        }
+    }
+}
+
+fn entry(text: &mut String, index: usize, depth: usize, position: Position) {
+    let depth = match position {
+        Position::Line => depth,
+        Position::Key | Position::Item => depth - 1,
+    };
+
+    match (index, position) {
+        (0, Position::Key) => {
+            text.push('\n');
+            text.push_str(&"  ".repeat(depth + 1));
+        }
+        (0, Position::Item) => text.push(' '),
+        (_, Position::Line) => text.push_str(&"  ".repeat(depth)),
+        _ => text.push_str(&"  ".repeat(depth + 1)),
+    }
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::Null => "null".into(),
+        Value::Bool(value) => value.to_string(),
+        Value::Number(value) => value.to_string(),
+        Value::String(value) => string(value),
+        Value::Array(_) => "[]".into(),
+        Value::Object(_) => "{}".into(),
+    }
+}
+
+fn string(value: &str) -> String {
+    let reserved = [
+        "null", "~", "true", "false", "yes", "no", "on", "off", "[]", "{}",
+    ];
+
+    let plain = !value.is_empty()
+        && value.trim() == value
+        && !value.chars().any(char::is_control)
+        && !value.starts_with([
+            '-', '?', ':', ',', '[', ']', '{', '}', '#', '&', '*', '!', '|', '>', '\'', '"', '%',
+            '@', '`',
+        ])
+        && !value.contains(": ")
+        && !value.contains(" #")
+        && !value.ends_with(':')
+        && !reserved.contains(&value.to_lowercase().as_str())
+        && value.parse::<f64>().is_err();
+
+    if plain {
+        value.into()
+    } else {
+        Value::String(value.into()).to_string()
+    }
+}
+
+pub fn read(text: &str) -> Result<Value, Error> {
+    // Blanked rather than dropped, so errors give the lines the user sees.
+    let text = text
+        .lines()
+        .map(|line| {
+            let ignored = line.trim_start().starts_with('#') || line.trim_end() == "---";
+
+            if ignored {
+                ""
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    value(&parse(&text)?)
+}
+
+fn value(node: &Node) -> Result<Value, Error> {
+    match &node.value {
+        Tree::Scalar(raw) => scalar_value(node, raw),
+        Tree::Sequence(items) => items.iter().map(value).collect::<Result<_, _>>(),
+        Tree::Map(entries) => {
+            let mut map = Map::new();
+
+            for (key, entry) in entries {
+                let key = match value(key)? {
+                    Value::String(key) => key,
+                    key => key.to_string(),
+                };
+
+                map.insert(key, value(entry)?);
+            }
+
+            Ok(Value::Object(map))
+        }
+    }
+}
+
+fn scalar_value(node: &Node, raw: &str) -> Result<Value, Error> {
+    if raw.starts_with('"') {
+        return serde_json::from_str(raw).map_err(|error| Error::Deserialize {
+            line: node.line,
+            column: node.column,
+            message: error.to_string(),
+        });
+    }
+
+    let value = match raw {
+        "null" | "~" => Value::Null,
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "[]" => Value::Array(vec![]),
+        "{}" => Value::Object(Map::new()),
+        _ => {
+            if let Ok(value) = raw.parse::<i64>() {
+                Value::from(value)
+            } else if let Ok(value) = raw.parse::<u64>() {
+                Value::from(value)
+            } else if let Some(value) = raw.parse::<f64>().ok().and_then(Number::from_f64) {
+                Value::Number(value)
+            } else {
+                Value::String(raw.into())
+            }
+        }
+    };
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    fn position(text: &str) -> (usize, usize) {
+        match read(text) {
+            Err(Error::Deserialize { line, column, .. }) => (line, column),
+            other => panic!("expected a deserialize error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn what_is_written_reads_back() {
+        let value = json!({
+            "name": "jago",
+            "version": 2,
+            "ratio": -0.5,
+            "draft": false,
+            "parent": null,
+            "empty": { "list": [], "map": {} },
+            "items": [1, [2, 3], { "four": 4 }],
+        });
+
+        assert_eq!(read(&write(&value)).unwrap(), value);
+    }
+
+    #[test]
+    fn strings_that_look_like_something_else_are_quoted() {
+        let strings = [
+            "",
+            " padded",
+            "true",
+            "No",
+            "~",
+            "12",
+            "1e3",
+            "- item",
+            "key: value",
+            "end:",
+            "# hash",
+            "line\nbreak",
+            "\"quoted\"",
+        ];
+
+        for string in strings {
+            let value = json!({ string: [string] });
+
+            assert_eq!(read(&write(&value)).unwrap(), value, "{:?}", string);
+        }
+    }
+
+    #[test]
+    fn comments_and_document_markers_are_ignored() {
+        let text = "---\n# about\nname: jago\n  # nested\nitems:\n  - 1\n";
+
+        assert_eq!(read(text).unwrap(), json!({ "name": "jago", "items": [1] }));
+    }
+
+    #[test]
+    fn errors_give_the_line_and_column_as_written() {
+        assert_eq!(position("# about\nname: jago\n    version: 2\n"), (3, 5));
+        assert_eq!(position("---\nitems:\n  - 1\n  two: 2\n"), (4, 3));
+        assert_eq!(position("name: \"open\n"), (1, 7));
+    }
+}
@@ -1,5 +1,10 @@
+use crate::Name;
+
 pub trait Directive {
     fn stop(&self) -> bool;
+
+    /// Whether anything on screen changed, so it has to be drawn again.
+    fn redraw(&self) -> bool;
 }
 
 pub trait Handle {
@@ -14,6 +19,16 @@ use bitflags::bitflags;
 bitflags! {
     pub struct Directives: u32 {
         const STOP = 0b00000001;
+        /// Draw again what is on screen.
+        const REDRAW = 0b00000010;
+        /// Move the text on screen by the lines in `Signal::Scroll`.
+        const SCROLL = 0b00000100;
+        /// Lay out again for the size in `Signal::Resize`.
+        const RESIZE = 0b00001000;
+        /// Show the document in `Signal::Focus` from its top.
+        const FOCUS_CHANGE = 0b00010000;
+        /// The document in `Signal::Save` is on disk.
+        const SAVE = 0b00100000;
     }
 }
 
@@ -21,4 +36,77 @@ impl Directive for Directives {
     fn stop(&self) -> bool {
         self.contains(Directives::STOP)
     }
+
+    fn redraw(&self) -> bool {
+        self.intersects(
+            Directives::REDRAW | Directives::SCROLL | Directives::RESIZE | Directives::FOCUS_CHANGE,
+        )
+    }
+}
+
+/// What a directive needs to be acted on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signal {
+    Scroll(isize),
+    Resize { columns: usize, rows: usize },
+    Focus(Name),
+    Save(Name),
+}
+
+impl Signal {
+    pub fn directive(&self) -> Directives {
+        match self {
+            Signal::Scroll(_) => Directives::SCROLL,
+            Signal::Resize { .. } => Directives::RESIZE,
+            Signal::Focus(_) => Directives::FOCUS_CHANGE,
+            Signal::Save(_) => Directives::SAVE,
+        }
+    }
+}
+
+/// Directives along with the signals that carry what they need, for a
+/// frontend to act on once it has handled an event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    pub directives: Directives,
+    pub signals: Vec<Signal>,
+}
+
+impl Reply {
+    /// Adds `signal`, and the directive it goes with.
+    pub fn signal(mut self, signal: Signal) -> Self {
+        self.directives |= signal.directive();
+        self.signals.push(signal);
+        self
+    }
+
+    pub fn merge(&mut self, other: Reply) {
+        self.directives |= other.directives;
+        self.signals.extend(other.signals);
+    }
+}
+
+impl Default for Reply {
+    fn default() -> Self {
+        Directives::empty().into()
+    }
+}
+
+impl From<Directives> for Reply {
+    fn from(directives: Directives) -> Self {
+        Self {
+            directives,
+            signals: vec![],
+        }
+    }
+}
+
+impl Directive for Reply {
+    fn stop(&self) -> bool {
+        self.directives.stop()
+    }
+
+    fn redraw(&self) -> bool {
+        self.directives.redraw()
+    }
 }
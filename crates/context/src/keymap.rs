@@ -0,0 +1,657 @@
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Environment {0}")]
+    Environment(#[from] environment::Error),
+    #[error("Io {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Toml {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Key {0}")]
+    Key(String),
+}
+
+use crate::{CmRDT, Context, Name, Op, Style};
+
+use ::{
+    bitflags::bitflags,
+    chrono::{DateTime, Utc},
+    serde::{de, Deserialize, Deserializer, Serialize},
+    std::{
+        collections::BTreeMap,
+        fmt::{self, Display},
+        fs,
+        str::FromStr,
+        time::Duration,
+    },
+};
+
+static DEFAULT: &str = include_str!("keymap.toml");
+
+bitflags! {
+    pub struct Modifiers: u8 {
+        const CONTROL = 0b001;
+        const ALT = 0b010;
+        const SHIFT = 0b100;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Code {
+    Char(char),
+    Enter,
+    Esc,
+    Backspace,
+    Delete,
+    Tab,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    F(u8),
+}
+
+/// A key and the modifiers held down with it, written like `ctrl-s`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Key {
+    pub code: Code,
+    pub modifiers: Modifiers,
+}
+
+impl Key {
+    /// Shift is left out for chars, which come in the case it makes.
+    pub fn new(code: Code, mut modifiers: Modifiers) -> Self {
+        if let Code::Char(_) = code {
+            modifiers.remove(Modifiers::SHIFT);
+        }
+
+        Self { code, modifiers }
+    }
+}
+
+impl FromStr for Key {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Error> {
+        let mut modifiers = Modifiers::empty();
+        let mut rest = text;
+
+        loop {
+            if let Some(after) = rest.strip_prefix("ctrl-").filter(|after| !after.is_empty()) {
+                modifiers |= Modifiers::CONTROL;
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("alt-").filter(|after| !after.is_empty())
+            {
+                modifiers |= Modifiers::ALT;
+                rest = after;
+            } else if let Some(after) = rest
+                .strip_prefix("shift-")
+                .filter(|after| !after.is_empty())
+            {
+                modifiers |= Modifiers::SHIFT;
+                rest = after;
+            } else {
+                break;
+            }
+        }
+
+        let mut chars = rest.chars();
+
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => Code::Char(c),
+            _ => match rest {
+                "space" => Code::Char(' '),
+                "enter" => Code::Enter,
+                "esc" => Code::Esc,
+                "backspace" => Code::Backspace,
+                "delete" => Code::Delete,
+                "tab" => Code::Tab,
+                "left" => Code::Left,
+                "right" => Code::Right,
+                "up" => Code::Up,
+                "down" => Code::Down,
+                "home" => Code::Home,
+                "end" => Code::End,
+                "pageup" => Code::PageUp,
+                "pagedown" => Code::PageDown,
+                _ => match rest.strip_prefix('f').map(str::parse) {
+                    Some(Ok(number)) => Code::F(number),
+                    _ => return Err(Error::Key(text.into())),
+                },
+            },
+        };
+
+        Ok(Key::new(code, modifiers))
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (Modifiers::CONTROL, "ctrl-"),
+            (Modifiers::ALT, "alt-"),
+            (Modifiers::SHIFT, "shift-"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}", name)?;
+            }
+        }
+
+        match self.code {
+            Code::Char(' ') => write!(f, "space"),
+            Code::Char(c) => write!(f, "{}", c),
+            Code::Enter => write!(f, "enter"),
+            Code::Esc => write!(f, "esc"),
+            Code::Backspace => write!(f, "backspace"),
+            Code::Delete => write!(f, "delete"),
+            Code::Tab => write!(f, "tab"),
+            Code::Left => write!(f, "left"),
+            Code::Right => write!(f, "right"),
+            Code::Up => write!(f, "up"),
+            Code::Down => write!(f, "down"),
+            Code::Home => write!(f, "home"),
+            Code::End => write!(f, "end"),
+            Code::PageUp => write!(f, "pageup"),
+            Code::PageDown => write!(f, "pagedown"),
+            Code::F(number) => write!(f, "f{}", number),
+        }
+    }
+}
+
+/// What a binding runs. Most stand for an op, and the rest for something
+/// the frontend does itself. Quitting is left to the terminal, as a page
+/// can't close its own tab.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Command {
+    Quit,
+    Save,
+    Undo,
+    Redo,
+    Reload,
+    Read,
+    SelectNext,
+    SplitLines,
+    Deselect,
+    MoveLeft,
+    MoveRight,
+    ExtendLeft,
+    ExtendRight,
+    Backspace,
+    DeleteForward,
+    Newline,
+    PageUp,
+    PageDown,
+    Bold,
+    Italic,
+    Underline,
+    Highlight,
+    NextDocument,
+    CloseDocument,
+    Search,
+    ToggleLog,
+    Bindings,
+}
+
+impl Command {
+    /// The op the command stands for in `context`, if it stands for one.
+    pub fn op(&self, context: &Context) -> Option<Op> {
+        let op = match self {
+            Command::Save => Op::Save,
+            Command::Undo => Op::Undo,
+            Command::Redo => Op::Redo,
+            Command::Reload => Op::Reload,
+            Command::Read => Op::Read,
+            Command::SelectNext => Op::SelectNext,
+            Command::SplitLines => Op::SplitLines,
+            Command::Deselect => Op::Deselect,
+            Command::MoveLeft => Op::Move(-1),
+            Command::MoveRight => Op::Move(1),
+            Command::ExtendLeft => Op::Extend(-1),
+            Command::ExtendRight => Op::Extend(1),
+            Command::Backspace => Op::Backspace,
+            Command::DeleteForward => Op::DeleteForward,
+            Command::Newline => Op::Type("\n".into()),
+            Command::Bold => Op::ToggleStyle(Style::Bold),
+            Command::Italic => Op::ToggleStyle(Style::Italic),
+            Command::Underline => Op::ToggleStyle(Style::Underline),
+            Command::Highlight => Op::ToggleStyle(Style::Highlight),
+            Command::NextDocument => Op::Switch(next(context)?),
+            Command::CloseDocument => Op::Close(context.current().clone()),
+            Command::Quit
+            | Command::PageUp
+            | Command::PageDown
+            | Command::Search
+            | Command::ToggleLog
+            | Command::Bindings => return None,
+        };
+
+        Some(op)
+    }
+
+    /// Applies the op the command stands for, returning whether it stands
+    /// for one.
+    pub fn apply(&self, context: &mut Context) -> bool {
+        match self.op(context) {
+            Some(op) => {
+                context.apply(op);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Commands are shown as they are written in the keymap file.
+serde_plain::derive_display_from_serialize!(Command);
+serde_plain::derive_fromstr_from_deserialize!(Command);
+
+/// The document after the current one, going round to the first.
+fn next(context: &Context) -> Option<Name> {
+    let names = context.names().collect::<Vec<_>>();
+    let current = names.iter().position(|name| *name == context.current())?;
+
+    Some(names[(current + 1) % names.len()].clone())
+}
+
+/// A binding as it is listed: its keys as they are written, and its command.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Binding {
+    pub keys: String,
+    pub command: Command,
+}
+
+/// The keymap file as written.
+#[derive(Debug, Deserialize)]
+struct Config {
+    leader: Option<String>,
+    timeout: Option<u64>,
+    #[serde(default)]
+    bindings: BTreeMap<String, Bound>,
+}
+
+/// What keys are bound to in the keymap file, where `none` takes away the
+/// binding they have in the default keymap.
+#[derive(Debug)]
+enum Bound {
+    None,
+    Command(Command),
+}
+
+impl<'de> Deserialize<'de> for Bound {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "none" => Ok(Bound::None),
+            name => name.parse().map(Bound::Command).map_err(de::Error::custom),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    timeout: Duration,
+    bindings: BTreeMap<Vec<Key>, Command>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::parse(DEFAULT, "").expect("the default keymap is valid")
+    }
+}
+
+/// The default keymap with `text`, written as `keymap.toml` is, over it.
+impl FromStr for Keymap {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Error> {
+        Self::parse(DEFAULT, text)
+    }
+}
+
+impl Keymap {
+    /// The default keymap with `keymap.toml` in the config directory over
+    /// it, if there is one.
+    pub fn load() -> Result<Self, Error> {
+        Self::source()?.parse()
+    }
+
+    /// What `keymap.toml` in the config directory has, or nothing if there
+    /// is no such file. The server hands this to the browser, which can't
+    /// read the config directory itself.
+    pub fn source() -> Result<String, Error> {
+        let path = environment::config()?.join("keymap.toml");
+
+        match path.is_file() {
+            true => Ok(fs::read_to_string(path)?),
+            false => Ok(String::new()),
+        }
+    }
+
+    /// Reads `base` and then `over` it, which can change the leader and the
+    /// timeout and rebind or unbind any keys.
+    fn parse(base: &str, over: &str) -> Result<Self, Error> {
+        let base = toml::from_str::<Config>(base)?;
+        let over = toml::from_str::<Config>(over)?;
+
+        let leader = over.leader.or(base.leader);
+        let timeout = over.timeout.or(base.timeout);
+
+        let leader = leader.as_deref().map(Key::from_str).transpose()?;
+
+        let mut bindings = BTreeMap::new();
+
+        // Keys are compared once read, so `leader i` in one file and
+        // `ctrl-g i` in the other are the same binding.
+        for (keys, bound) in base.bindings.into_iter().chain(over.bindings) {
+            let keys = keys
+                .split_whitespace()
+                .map(|key| match (key, leader) {
+                    ("leader", Some(leader)) => Ok(leader),
+                    (key, _) => key.parse(),
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+
+            match bound {
+                Bound::Command(command) => bindings.insert(keys, command),
+                Bound::None => bindings.remove(&keys),
+            };
+        }
+
+        Ok(Self {
+            timeout: Duration::from_millis(timeout.unwrap_or_default()),
+            bindings,
+        })
+    }
+
+    /// How long a sequence may be left unfinished.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Every binding, ordered by its keys.
+    pub fn bindings(&self) -> Vec<Binding> {
+        self.bindings
+            .iter()
+            .map(|(keys, command)| Binding {
+                keys: keys
+                    .iter()
+                    .map(Key::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                command: command.clone(),
+            })
+            .collect()
+    }
+
+    /// Adds `key` to the keys pressed so far, dropping them first if they
+    /// were left for too long.
+    pub fn press(&self, chords: &mut Chords, key: Key) -> Press {
+        if self.expired(chords) {
+            chords.keys.clear();
+        }
+
+        chords.keys.push(key);
+        chords.at = Some(Utc::now());
+
+        let longer = self
+            .bindings
+            .range(chords.keys.clone()..)
+            .nth(usize::from(self.bindings.contains_key(&chords.keys)))
+            .is_some_and(|(keys, _)| keys.starts_with(&chords.keys));
+
+        if longer {
+            return Press::Pending;
+        }
+
+        let keys = std::mem::take(&mut chords.keys);
+
+        match self.bindings.get(&keys) {
+            Some(command) => Press::Command(command.clone()),
+            None => Press::Unbound(keys),
+        }
+    }
+
+    /// Ends a sequence left unfinished for too long, running the command
+    /// bound to the keys pressed so far if there is one.
+    pub fn expire(&self, chords: &mut Chords) -> Option<Press> {
+        if chords.keys.is_empty() || !self.expired(chords) {
+            return None;
+        }
+
+        let keys = std::mem::take(&mut chords.keys);
+
+        match self.bindings.get(&keys) {
+            Some(command) => Some(Press::Command(command.clone())),
+            None => Some(Press::Unbound(keys)),
+        }
+    }
+
+    fn expired(&self, chords: &Chords) -> bool {
+        chords.at.is_some_and(|at| {
+            (Utc::now() - at)
+                .to_std()
+                .is_ok_and(|elapsed| elapsed > self.timeout)
+        })
+    }
+}
+
+/// The keys of a sequence pressed so far, kept by each frontend.
+#[derive(Debug, Clone, Default)]
+pub struct Chords {
+    keys: Vec<Key>,
+    at: Option<DateTime<Utc>>,
+}
+
+impl Chords {
+    pub fn keys(&self) -> &[Key] {
+        &self.keys
+    }
+}
+
+/// What pressing a key came to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Press {
+    Command(Command),
+    /// The keys so far start a longer sequence.
+    Pending,
+    /// No binding starts with the keys, which frontends type if they are
+    /// a single char.
+    Unbound(Vec<Key>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(text: &str) -> Key {
+        text.parse().unwrap()
+    }
+
+    fn press(keymap: &Keymap, chords: &mut Chords, keys: &str) -> Vec<Press> {
+        keys.split_whitespace()
+            .map(|text| keymap.press(chords, key(text)))
+            .collect()
+    }
+
+    #[test]
+    fn keys_read_as_they_are_written() {
+        for text in [
+            "a",
+            "ctrl-s",
+            "ctrl-alt-x",
+            "shift-left",
+            "space",
+            "f12",
+            "-",
+        ] {
+            assert_eq!(key(text).to_string(), text);
+        }
+
+        assert_eq!(key("shift-a"), key("a"));
+        assert_eq!(key("alt-ctrl-x"), key("ctrl-alt-x"));
+        assert_eq!(key("ctrl--").code, Code::Char('-'));
+
+        for text in ["", "ctrl-", "nope", "fx", "f300"] {
+            assert!(text.parse::<Key>().is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn the_default_keymap_spells_out_its_leader() {
+        let keymap = Keymap::default();
+
+        assert_eq!(keymap.timeout(), Duration::from_millis(1000));
+        assert!(keymap.bindings().contains(&Binding {
+            keys: "ctrl-g i".into(),
+            command: Command::Italic,
+        }));
+        assert!(!keymap
+            .bindings()
+            .iter()
+            .any(|binding| binding.keys.contains("leader")));
+    }
+
+    #[test]
+    fn files_over_the_default_can_rebind_keys_and_the_leader() {
+        let keymap = r#"
+            leader = "ctrl-space"
+            timeout = 50
+
+            [bindings]
+            "ctrl-s" = "quit"
+            "leader leader" = "save"
+        "#
+        .parse::<Keymap>()
+        .unwrap();
+
+        let mut chords = Chords::default();
+
+        assert_eq!(keymap.timeout(), Duration::from_millis(50));
+        assert_eq!(
+            press(&keymap, &mut chords, "ctrl-s"),
+            vec![Press::Command(Command::Quit)]
+        );
+        assert_eq!(
+            press(&keymap, &mut chords, "ctrl-space i"),
+            vec![Press::Pending, Press::Command(Command::Italic)]
+        );
+        assert_eq!(
+            press(&keymap, &mut chords, "ctrl-space ctrl-space"),
+            vec![Press::Pending, Press::Command(Command::Save)]
+        );
+
+        assert!("leader = \"nope\"".parse::<Keymap>().is_err());
+        assert!("[bindings]\n\"ctrl-s\" = \"fly\""
+            .parse::<Keymap>()
+            .is_err());
+    }
+
+    #[test]
+    fn keys_bound_to_none_lose_their_default_binding() {
+        let keymap = r#"
+            [bindings]
+            "ctrl-k" = "none"
+            "leader i" = "none"
+            "ctrl-q" = "none"
+        "#
+        .parse::<Keymap>()
+        .unwrap();
+
+        let mut chords = Chords::default();
+
+        assert_eq!(
+            press(&keymap, &mut chords, "ctrl-k"),
+            vec![Press::Unbound(vec![key("ctrl-k")])]
+        );
+        assert_eq!(
+            press(&keymap, &mut chords, "ctrl-g i"),
+            vec![
+                Press::Pending,
+                Press::Unbound(vec![key("ctrl-g"), key("i")])
+            ]
+        );
+        assert!(!keymap
+            .bindings()
+            .iter()
+            .any(|binding| binding.command == Command::Highlight));
+        assert_eq!(
+            keymap.bindings().len(),
+            Keymap::default().bindings().len() - 2
+        );
+    }
+
+    #[test]
+    fn commands_are_shown_as_they_are_written() {
+        for binding in Keymap::default().bindings() {
+            let name = binding.command.to_string();
+
+            assert_eq!(name.parse::<Command>().unwrap(), binding.command);
+            assert!(DEFAULT.contains(&format!("\"{}\"", name)), "{}", name);
+        }
+
+        assert_eq!(Command::SelectNext.to_string(), "select-next");
+    }
+
+    #[test]
+    fn sequences_wait_for_their_next_key() {
+        let keymap = Keymap::default();
+        let mut chords = Chords::default();
+
+        assert_eq!(press(&keymap, &mut chords, "ctrl-g"), vec![Press::Pending]);
+        assert_eq!(chords.keys(), [key("ctrl-g")]);
+
+        assert_eq!(
+            press(&keymap, &mut chords, "u"),
+            vec![Press::Command(Command::Underline)]
+        );
+        assert!(chords.keys().is_empty());
+
+        assert_eq!(
+            press(&keymap, &mut chords, "ctrl-g z"),
+            vec![
+                Press::Pending,
+                Press::Unbound(vec![key("ctrl-g"), key("z")])
+            ]
+        );
+        assert_eq!(
+            press(&keymap, &mut chords, "x"),
+            vec![Press::Unbound(vec![key("x")])]
+        );
+    }
+
+    #[test]
+    fn sequences_left_too_long_are_dropped() {
+        let keymap = r#"
+            timeout = 100
+
+            [bindings]
+            "ctrl-x" = "save"
+            "ctrl-x ctrl-x" = "quit"
+        "#
+        .parse::<Keymap>()
+        .unwrap();
+
+        let mut chords = Chords::default();
+
+        assert_eq!(press(&keymap, &mut chords, "ctrl-x"), vec![Press::Pending]);
+        assert_eq!(keymap.expire(&mut chords), None);
+
+        std::thread::sleep(Duration::from_millis(150));
+
+        assert_eq!(
+            keymap.expire(&mut chords),
+            Some(Press::Command(Command::Save))
+        );
+        assert_eq!(keymap.expire(&mut chords), None);
+
+        // A key pressed after the timeout starts a new sequence.
+        assert_eq!(press(&keymap, &mut chords, "ctrl-x"), vec![Press::Pending]);
+
+        std::thread::sleep(Duration::from_millis(150));
+
+        assert_eq!(press(&keymap, &mut chords, "ctrl-x"), vec![Press::Pending]);
+    }
+}
@@ -1,5 +1,15 @@
 mod buffer;
+mod directory;
+mod document;
+pub mod encoding;
+mod format;
 mod handle;
+mod keymap;
+mod log;
+mod store;
+mod view;
+#[cfg(not(target_arch = "wasm32"))]
+mod watch;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -9,34 +19,331 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error("Encoding {0}")]
     Encoding(#[from] std::string::FromUtf8Error),
+    #[error("Buffer {0}")]
+    Buffer(#[from] buffer::Error),
+    #[error("Directory {0}")]
+    Directory(#[from] directory::Error),
+    #[error("Format {0}")]
+    Format(#[from] format::Error),
+    #[error("Store {0}")]
+    Store(#[from] store::Error),
+    #[error("Log {0}")]
+    Log(#[from] log::Error),
+    #[error("Keymap {0}")]
+    Keymap(#[from] keymap::Error),
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("Watch {0}")]
+    Watch(#[from] watch::Error),
+    #[error("NoPath")]
+    NoPath,
+    #[error("NoSearch")]
+    NoSearch,
+    #[error("NoBuffer {0}")]
+    NoBuffer(Name),
+    #[error("Unsaved {0}")]
+    Unsaved(Name),
+    #[error("Permanent {0}")]
+    Permanent(Name),
+    #[error("AlreadyOpen {0}")]
+    AlreadyOpen(Name),
+    #[error("Unaddressable")]
+    Unaddressable,
 }
 
-pub use handle::{Directive, Directives, Handle};
+pub use buffer::{
+    width, Actor, Anchor, Annotation, Buffer, Case, Change, Cursor, Direction, Op as BufferOp,
+    Position, Query, Selection, Snapshot, Style, Tag,
+};
+pub use crdts::CmRDT;
+pub use directory::{Entry, Kind};
+pub use document::{Document, Name};
+pub use format::Format;
+pub use handle::{Directive, Directives, Handle, Reply, Signal};
+pub use keymap::{Binding, Chords, Code, Command, Key, Keymap, Modifiers, Press};
+pub use log::{Filter, Log, Note, Op as LogOp, Severity, Source};
+pub use view::{Frame, Line, Look, Panel, Span, Status, Tab, View};
 
-use buffer::Buffer;
+use directory::Directory;
+use store::Store;
+#[cfg(not(target_arch = "wasm32"))]
+use watch::Watcher;
 
 use ::{
-    crdts::CmRDT,
+    crdts::list,
     instrument::prelude::*,
-    std::{fmt::Display, fs::read_dir, io::Read},
+    serde::{de::DeserializeOwned, Deserialize, Serialize},
+    std::{
+        collections::BTreeMap,
+        fmt::{self, Display},
+        fs::{self, File},
+        io::Write,
+        ops::Range,
+        path::{Path, PathBuf},
+    },
+    tokio::sync::broadcast,
 };
 
-static DEFAULT_ACTOR: u8 = 0;
+static DEFAULT_ACTOR: Actor = 0;
+
+/// How many events or changes a subscriber may fall behind by before it
+/// misses some.
+static BACKLOG: usize = 1024;
 
+/// A session: every open document by name, the one being edited, and what
+/// they share, the copy directory listing, the log and the watcher.
 pub struct Context {
-    buffer: Buffer,
+    actor: Actor,
+    documents: BTreeMap<Name, Document>,
+    current: Name,
+    directory: Directory,
+    log: Log,
+    events: broadcast::Sender<Event>,
+    #[cfg(not(target_arch = "wasm32"))]
+    watcher: Option<Watcher>,
 }
 
 impl Context {
     pub fn new() -> Self {
+        Self::with_scratch(Document::new(Buffer::new(), None))
+    }
+
+    fn with_scratch(scratch: Document) -> Self {
         Self {
-            buffer: Buffer::new(),
+            actor: DEFAULT_ACTOR,
+            documents: BTreeMap::from([(Name::Scratch, scratch)]),
+            current: Name::Scratch,
+            directory: Directory::default(),
+            log: Log::default(),
+            events: broadcast::channel(BACKLOG).0,
+            #[cfg(not(target_arch = "wasm32"))]
+            watcher: None,
+        }
+    }
+
+    /// Picks the scratch buffer back up from where the last session left it.
+    pub fn restore() -> Result<Self, Error> {
+        let mut store = Store::open("scratch")?;
+
+        Ok(Self::with_scratch(Document::new(
+            store.load()?,
+            Some(store),
+        )))
+    }
+
+    pub fn actor(&self) -> Actor {
+        self.actor
+    }
+
+    /// The names of the open documents, in order.
+    pub fn names(&self) -> impl Iterator<Item = &Name> {
+        self.documents.keys()
+    }
+
+    pub fn document(&self, name: &Name) -> Option<&Document> {
+        self.documents.get(name)
+    }
+
+    /// The name of the document being edited, which ops go to unless they
+    /// are sent elsewhere with `To`.
+    pub fn current(&self) -> &Name {
+        &self.current
+    }
+
+    pub fn focused(&self) -> &Document {
+        &self.documents[&self.current]
+    }
+
+    fn focused_mut(&mut self) -> &mut Document {
+        self.documents
+            .get_mut(&self.current)
+            .expect("the current document is open")
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        self.focused().buffer()
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        self.directory.entries()
+    }
+
+    pub fn log(&self) -> &Log {
+        &self.log
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.focused().path()
+    }
+
+    pub fn dirty(&self) -> bool {
+        self.focused().dirty()
+    }
+
+    pub fn conflicted(&self) -> bool {
+        self.focused().conflicted()
+    }
+
+    pub fn search(&self) -> Option<&Query> {
+        self.focused().search()
+    }
+
+    pub fn matches(&self) -> Vec<Range<usize>> {
+        self.focused().matches()
+    }
+
+    /// Lays the focused document out for drawing, with what the frontend
+    /// keeps between draws in `frame`.
+    pub fn view(&self, frame: &Frame) -> Result<View, Error> {
+        view::view(self, frame)
+    }
+
+    /// Hears of every event from here on. A subscriber that falls too far
+    /// behind is told it lagged, and should read everything again.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// Starts watching the copy directory and every open file, for `Refresh`
+    /// to pick up changes made outside.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn watch(&mut self) -> Result<(), Error> {
+        let mut watcher = Watcher::new()?;
+
+        let root = environment::copy_directory()?;
+
+        if root.is_dir() {
+            watcher.directory(&root)?;
+        }
+
+        self.watcher = Some(watcher);
+
+        self.follow()
+    }
+
+    /// Whether anything watched has changed since the last `Refresh`.
+    pub fn changed(&mut self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(watcher) = &mut self.watcher {
+            return watcher.poll();
+        }
+
+        false
+    }
+
+    pub fn format(&self) -> Format {
+        self.focused().format()
+    }
+
+    /// Writes `value` over the focused document in `format`, as an edit to
+    /// it like any other.
+    pub fn render<T: ?Sized + Serialize>(
+        &mut self,
+        format: Format,
+        value: &T,
+    ) -> Result<(), Error> {
+        let text = format.write(value)?;
+
+        let actor = self.actor;
+        let document = self.focused_mut();
+
+        document.rewrite(&text, actor)?;
+        document.set_format(format);
+
+        Ok(())
+    }
+
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        Ok(self.format().read(&self.buffer().to_string())?)
+    }
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.buffer().fmt(f)
+    }
+}
+
+/// What happened to the session, sent to its subscribers after each op.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Event {
+    Changed(Name, Change),
+    Opened(Name),
+    Closed(Name),
+    Switched(Name),
+    /// The document was saved, edited for the first time since, or changed
+    /// on disk while it had unsaved edits.
+    Status {
+        name: Name,
+        dirty: bool,
+        conflicted: bool,
+    },
+    Logged(Note),
+    Saved(Name),
+}
+
+/// What a frontend showing the session does about an event.
+impl From<&Event> for Reply {
+    fn from(event: &Event) -> Self {
+        let reply = Reply::from(Directives::REDRAW);
+
+        match event {
+            Event::Switched(name) => reply.signal(Signal::Focus(name.clone())),
+            Event::Saved(name) => reply.signal(Signal::Save(name.clone())),
+            _ => reply,
         }
     }
 }
 
 pub enum Op {
     Read,
+    Expand(PathBuf),
+    Collapse(PathBuf),
+    Insert(usize, String),
+    Delete(Range<usize>),
+    Replace(Range<usize>, String),
+    Move(isize),
+    Extend(isize),
+    Select(Range<usize>),
+    Deselect,
+    SelectNext,
+    SplitLines,
+    Mark(String),
+    Unmark(String),
+    Jump(String),
+    Type(String),
+    Paste(String),
+    Backspace,
+    DeleteForward,
+    Open(PathBuf),
+    Save,
+    SaveAs(PathBuf),
+    Undo,
+    Redo,
+    Refresh,
+    Reload,
+    Search(Query),
+    FindNext,
+    FindPrevious,
+    ReplaceNext(String),
+    ReplaceAll(String),
+    EndSearch,
+    Annotate(Range<usize>, Style),
+    Unstyle(Range<usize>, Style),
+    RemoveAnnotation(Tag),
+    ToggleStyle(Style),
+    /// Makes the named document current, opening it first if need be.
+    Switch(Name),
+    Close(Name),
+    /// Applies an op to the named document without switching to it.
+    To(Name, Box<Op>),
+    /// Adds a note recorded by another replica to the log.
+    Log(LogOp),
 }
 
 impl CmRDT for Context {
@@ -44,36 +351,712 @@ impl CmRDT for Context {
     type Validation = Error;
 
     fn validate_op(&self, op: &Self::Op) -> Result<(), Error> {
-        Ok(())
+        match op {
+            Op::Read | Op::Expand(_) | Op::Collapse(_) => Ok(()),
+            Op::Open(_) | Op::SaveAs(_) | Op::Refresh | Op::Switch(_) => Ok(()),
+            Op::Close(Name::Scratch) => Err(Error::Permanent(Name::Scratch)),
+            Op::Close(name) => match self.documents.get(name) {
+                Some(document) if document.dirty() => Err(Error::Unsaved(name.clone())),
+                Some(_) => Ok(()),
+                None => Err(Error::NoBuffer(name.clone())),
+            },
+            Op::To(name, op) => self
+                .documents
+                .get(name)
+                .ok_or_else(|| Error::NoBuffer(name.clone()))?
+                .validate(op),
+            Op::Log(op) => Ok(self.log.validate_op(op)?),
+            op => self.focused().validate(op),
+        }
     }
 
     fn apply(&mut self, op: Self::Op) {
-        match op {
-            Op::Read => {
-                if let Err(error) = self.read() {
-                    //let op = self.errors.append(error, self.actor);
+        let actor = self.actor;
+        let before = self.states();
 
-                    //self.errors.apply(op);
-                }
+        let source = match &op {
+            Op::Read | Op::Expand(_) | Op::Collapse(_) => Source::Listing,
+            Op::Open(_) | Op::Refresh | Op::Log(_) => Source::Session,
+            Op::Switch(name) | Op::Close(name) | Op::To(name, _) => Source::Document(name.clone()),
+            _ => Source::Document(self.current.clone()),
+        };
+
+        let saving = match &op {
+            Op::Save | Op::SaveAs(_) => Some(self.current.clone()),
+            Op::To(name, op) if matches!(**op, Op::Save | Op::SaveAs(_)) => Some(name.clone()),
+            _ => None,
+        };
+
+        let result = match op {
+            Op::Read => self.switch(Name::Listing),
+            Op::Expand(path) => {
+                self.directory.expand(path);
+                self.read()
+            }
+            Op::Collapse(path) => {
+                self.directory.collapse(&path);
+                self.read()
+            }
+            Op::Open(path) => resolve(path).and_then(|path| self.switch(Name::File(path))),
+            Op::SaveAs(path) => self.save_as(path),
+            Op::Refresh => self.refresh(),
+            Op::Switch(name) => self.switch(name),
+            Op::Close(name) => self.close(&name),
+            Op::To(name, op) => match self.documents.get_mut(&name) {
+                Some(document) => document.apply(*op, actor),
+                None => Err(Error::NoBuffer(name)),
+            },
+            Op::Log(op) => {
+                self.note(op);
+                Ok(())
             }
+            op => self.focused_mut().apply(op, actor),
+        };
+
+        let saved = match result.and_then(|_| self.persist()) {
+            // Saving as another path renames the current file.
+            Ok(()) => saving.map(|name| match self.documents.contains_key(&name) {
+                true => name,
+                false => self.current.clone(),
+            }),
+            Err(error) => {
+                self.fail(source, error);
+                None
+            }
+        };
+
+        if let Some(name) = &saved {
+            self.record(
+                Severity::Info,
+                Source::Document(name.clone()),
+                "saved".into(),
+            );
+        }
+
+        self.publish(before);
+
+        if let Some(name) = saved {
+            notify(&self.events, Event::Saved(name));
         }
     }
 }
 
+/// What `Event::Status` reports of a document, and which document is
+/// current, to compare before and after an op.
+type States = (BTreeMap<Name, (bool, bool)>, Name);
+
 impl Context {
+    /// Makes `name` current, reading the listing or the log again, or opening
+    /// the file, if it isn't open already.
+    fn switch(&mut self, name: Name) -> Result<(), Error> {
+        if let Name::File(path) = &name {
+            let open = self
+                .documents
+                .iter()
+                .find(|(_, document)| document.path() == Some(path))
+                .map(|(name, _)| name.clone());
+
+            if let Some(open) = open {
+                self.current = open;
+
+                return Ok(());
+            }
+        }
+
+        match &name {
+            Name::Scratch => {
+                self.documents
+                    .entry(Name::Scratch)
+                    .or_insert_with(|| Document::new(Buffer::new(), None));
+            }
+            Name::Listing => {
+                self.documents
+                    .entry(Name::Listing)
+                    .or_insert_with(|| Document::new(Buffer::new(), None));
+
+                self.current = Name::Listing;
+
+                return self.read();
+            }
+            Name::Log => {
+                self.documents
+                    .entry(Name::Log)
+                    .or_insert_with(|| Document::new(Buffer::new(), None));
+
+                self.show_log()?;
+            }
+            Name::File(path) => {
+                let document = Document::open(path.clone(), self.actor)?;
+
+                self.documents.insert(name.clone(), document);
+                self.current = name;
+
+                return self.follow();
+            }
+        }
+
+        self.current = name;
+
+        Ok(())
+    }
+
+    /// Closes `name`, going back to the scratch buffer if it was current.
+    fn close(&mut self, name: &Name) -> Result<(), Error> {
+        if *name == Name::Scratch {
+            return Err(Error::Permanent(Name::Scratch));
+        }
+
+        let mut document = self
+            .documents
+            .remove(name)
+            .ok_or_else(|| Error::NoBuffer(name.clone()))?;
+
+        if document.dirty() {
+            self.documents.insert(name.clone(), document);
+
+            return Err(Error::Unsaved(name.clone()));
+        }
+
+        document.persist()?;
+
+        if self.current == *name {
+            self.current = Name::Scratch;
+        }
+
+        self.follow()
+    }
+
+    /// Saves the current document to `path`. A file is known by its new path
+    /// from then on, while other documents keep their names and just gain a
+    /// path to save to. Saving over a file open in another document is
+    /// refused rather than leave the two at odds.
+    fn save_as(&mut self, path: PathBuf) -> Result<(), Error> {
+        let path = resolve(path)?;
+        let name = Name::File(path.clone());
+
+        if name != self.current && self.documents.contains_key(&name) {
+            return Err(Error::AlreadyOpen(name));
+        }
+
+        self.focused_mut().save_as(path.clone())?;
+
+        if let Name::File(_) = self.current {
+            if let Some(document) = self.documents.remove(&self.current) {
+                self.current = Name::File(path);
+                self.documents.insert(self.current.clone(), document);
+            }
+        }
+
+        self.follow()
+    }
+
+    /// Points the watcher at the files now open.
+    fn follow(&mut self) -> Result<(), Error> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(watcher) = &mut self.watcher {
+            let paths = self
+                .documents
+                .values()
+                .filter_map(|document| document.path().map(Path::to_path_buf))
+                .collect();
+
+            watcher.documents(paths)?;
+        }
+
+        Ok(())
+    }
+
+    /// Brings in what changed outside: the listing is read again if it is
+    /// open and untouched, and each open file merged in unless it has unsaved
+    /// edits, in which case it is flagged as conflicted instead.
+    fn refresh(&mut self) -> Result<(), Error> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(watcher) = &mut self.watcher {
+            let changed = watcher.take();
+
+            let root = environment::copy_directory()?;
+
+            let listing = self
+                .documents
+                .get(&Name::Listing)
+                .is_some_and(|listing| !listing.dirty());
+
+            if listing && changed.iter().any(|path| path.starts_with(&root)) {
+                self.read()?;
+            }
+
+            let actor = self.actor;
+            let mut merged = vec![];
+
+            for (name, document) in &mut self.documents {
+                if document.path().is_some_and(|path| changed.contains(path)) {
+                    document.merge(actor)?;
+                    merged.push((name.clone(), document.conflicted()));
+                }
+            }
+
+            for (name, conflicted) in merged {
+                match conflicted {
+                    true => self.record(
+                        Severity::Warning,
+                        Source::Document(name),
+                        "changed on disk while it has unsaved edits".into(),
+                    ),
+                    false => self.record(
+                        Severity::Info,
+                        Source::Document(name),
+                        "changed on disk and merged in".into(),
+                    ),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn persist(&mut self) -> Result<(), Error> {
+        for document in self.documents.values_mut() {
+            document.persist()?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the copy directory into the listing, if it is open.
     fn read(&mut self) -> Result<(), Error> {
         let root = environment::copy_directory()?;
 
-        let read = read_dir(root)?;
+        for error in self.directory.read(&root) {
+            warn!("{}", error);
 
-        for entry in read.filter_map(Result::ok) {
-            self.write_row(entry.path().display());
+            self.record(Severity::Warning, Source::Listing, error.to_string());
+        }
+
+        let listing = self.directory.to_string();
+        let actor = self.actor;
+
+        if let Some(document) = self.documents.get_mut(&Name::Listing) {
+            document.load(&listing, actor)?;
         }
 
         Ok(())
     }
 
-    fn write_row(&mut self, item: impl Display) {
-        // ...
+    /// Writes the notes into the log document, if it is open.
+    fn show_log(&mut self) -> Result<(), Error> {
+        let log = self
+            .log
+            .iter()
+            .map(|note| format!("{}\n", note))
+            .collect::<String>();
+
+        let actor = self.actor;
+
+        if let Some(document) = self.documents.get_mut(&Name::Log) {
+            document.load(&log, actor)?;
+        }
+
+        Ok(())
+    }
+
+    fn states(&self) -> States {
+        let states = self
+            .documents
+            .iter()
+            .map(|(name, document)| (name.clone(), (document.dirty(), document.conflicted())))
+            .collect();
+
+        (states, self.current.clone())
+    }
+
+    /// Sends what the last op did: the documents it closed or opened, the
+    /// changes to each buffer, the documents whose status changed, and
+    /// lastly the switch to another document.
+    fn publish(&mut self, (before, current): States) {
+        let (after, _) = self.states();
+
+        let mut events = before
+            .keys()
+            .filter(|name| !after.contains_key(name))
+            .map(|name| Event::Closed(name.clone()))
+            .chain(
+                after
+                    .keys()
+                    .filter(|name| !before.contains_key(name))
+                    .map(|name| Event::Opened(name.clone())),
+            )
+            .collect::<Vec<_>>();
+
+        for (name, document) in &mut self.documents {
+            events.extend(
+                document
+                    .changes()
+                    .into_iter()
+                    .map(|change| Event::Changed(name.clone(), change)),
+            );
+        }
+
+        for (name, state) in after {
+            if before.get(&name) == Some(&state) {
+                continue;
+            }
+
+            let (dirty, conflicted) = state;
+
+            events.push(Event::Status {
+                name,
+                dirty,
+                conflicted,
+            });
+        }
+
+        if self.current != current {
+            events.push(Event::Switched(self.current.clone()));
+        }
+
+        for event in events {
+            notify(&self.events, event);
+        }
+    }
+
+    fn fail(&mut self, source: Source, error: Error) {
+        error!("{}", error);
+
+        self.record(Severity::Error, source, error.to_string());
+    }
+
+    fn record(&mut self, severity: Severity, source: Source, message: String) {
+        let op = self.log.record(severity, source, message, self.actor);
+
+        self.note(op);
+    }
+
+    /// Adds a note to the log, telling subscribers and the log document. A
+    /// note after every other one only needs its line added to the end.
+    fn note(&mut self, op: LogOp) {
+        let line = match &op {
+            list::Op::Insert { val, .. } => {
+                notify(&self.events, Event::Logged(val.clone()));
+
+                self.log.appends(&op).then(|| format!("{}\n", val))
+            }
+            list::Op::Delete { .. } => return,
+        };
+
+        self.log.apply(op);
+
+        let actor = self.actor;
+
+        let shown = match (line, self.documents.get_mut(&Name::Log)) {
+            (Some(line), Some(document)) => document.append(&line, actor),
+            (None, Some(_)) => self.show_log(),
+            (_, None) => Ok(()),
+        };
+
+        if let Err(error) = shown {
+            error!("logging: {}", error);
+        }
+    }
+}
+
+fn resolve(path: PathBuf) -> Result<PathBuf, Error> {
+    if path.is_absolute() {
+        Ok(path)
+    } else {
+        Ok(environment::copy_directory()?.join(path))
+    }
+}
+
+/// Tells subscribers of `value`. Sending only fails when there are none,
+/// which is fine.
+fn notify<T>(sender: &broadcast::Sender<T>, value: T) {
+    let _ = sender.send(value);
+}
+
+fn write_atomic(path: &Path, content: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+
+    let temporary = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+
+    let written = File::create(&temporary).and_then(|mut file| {
+        file.write_all(content.as_ref())?;
+        file.sync_all()
+    });
+
+    if let Err(error) = written.and_then(|_| fs::rename(&temporary, path)) {
+        let _ = fs::remove_file(&temporary);
+
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{
+            thread,
+            time::{Duration, Instant},
+        },
+    };
+
+    #[test]
+    fn insert_delete_and_replace() {
+        let mut context = Context::new();
+
+        context.apply(Op::Insert(0, "hello".into()));
+        context.apply(Op::Insert(5, " world".into()));
+        assert_eq!(context.buffer().to_string(), "hello world");
+
+        context.apply(Op::Delete(0..6));
+        assert_eq!(context.buffer().to_string(), "world");
+
+        context.apply(Op::Replace(0..5, "there".into()));
+        assert_eq!(context.buffer().to_string(), "there");
+
+        context.apply(Op::Replace(5..5, "!".into()));
+        assert_eq!(context.buffer().to_string(), "there!");
+    }
+
+    #[test]
+    fn out_of_range_edits_are_rejected_and_logged() {
+        let mut context = Context::new();
+
+        context.apply(Op::Insert(0, "abc".into()));
+
+        assert!(context.validate_op(&Op::Insert(4, "x".into())).is_err());
+        assert!(context.validate_op(&Op::Delete(2..4)).is_err());
+        assert!(context.validate_op(&Op::Replace(3..9, "x".into())).is_err());
+        assert!(context.validate_op(&Op::Delete(1..3)).is_ok());
+
+        context.apply(Op::Delete(2..4));
+
+        assert_eq!(context.buffer().to_string(), "abc");
+        assert!(context
+            .log()
+            .iter()
+            .any(|note| note.severity == Severity::Error));
+    }
+
+    #[test]
+    fn inserts_count_chars_rather_than_bytes() {
+        let mut context = Context::new();
+
+        context.apply(Op::Insert(0, "héllo".into()));
+        context.apply(Op::Insert(2, "·".into()));
+        context.apply(Op::Delete(0..1));
+
+        assert_eq!(context.buffer().to_string(), "é·llo");
+    }
+
+    #[test]
+    fn saves_are_logged_by_the_context() {
+        let mut context = Context::new();
+        let path = std::env::temp_dir().join(format!("jago-saved-{}", std::process::id()));
+
+        let saved = |context: &Context| {
+            context
+                .log()
+                .iter()
+                .filter(|note| note.severity == Severity::Info && note.message == "saved")
+                .count()
+        };
+
+        context.apply(Op::Insert(0, "text".into()));
+        context.apply(Op::Save);
+
+        assert_eq!(saved(&context), 0);
+
+        context.apply(Op::SaveAs(path.clone()));
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "text");
+        assert_eq!(saved(&context), 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn saving_over_a_file_open_elsewhere_is_refused() {
+        let directory = std::env::temp_dir().join(format!("jago-save-as-{}", std::process::id()));
+        let (first, second) = (directory.join("first"), directory.join("second"));
+
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(&first, "first").unwrap();
+        fs::write(&second, "second").unwrap();
+
+        let mut context = Context::new();
+
+        context.apply(Op::Open(first.clone()));
+        context.apply(Op::Insert(0, "unsaved ".into()));
+        context.apply(Op::Open(second.clone()));
+        context.apply(Op::SaveAs(first.clone()));
+
+        assert!(context
+            .log()
+            .iter()
+            .any(|note| note.severity == Severity::Error
+                && note.message == Error::AlreadyOpen(Name::File(first.clone())).to_string()));
+        assert_eq!(fs::read_to_string(&first).unwrap(), "first");
+        assert_eq!(context.current(), &Name::File(second.clone()));
+
+        let document = &context.documents[&Name::File(first.clone())];
+
+        assert_eq!(document.buffer().to_string(), "unsaved first");
+        assert!(document.dirty());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn watched_files_are_merged_unless_they_have_unsaved_edits() {
+        let directory = std::env::temp_dir().join(format!("jago-watched-{}", std::process::id()));
+        let (clean, edited) = (directory.join("clean"), directory.join("edited"));
+
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(&clean, "clean\n").unwrap();
+        fs::write(&edited, "edited\n").unwrap();
+
+        let mut context = Context::new();
+
+        context.watch().unwrap();
+        context.apply(Op::Open(clean.clone()));
+        context.apply(Op::Open(edited.clone()));
+        context.apply(Op::Insert(0, "unsaved ".into()));
+
+        fs::write(&clean, "clean\noutside\n").unwrap();
+        fs::write(&edited, "edited\noutside\n").unwrap();
+
+        let state = |context: &Context, path: &Path| {
+            let document = context.document(&Name::File(path.into())).unwrap();
+
+            (
+                document.buffer().to_string(),
+                document.dirty(),
+                document.conflicted(),
+            )
+        };
+
+        let merged = ("clean\noutside\n".to_string(), false, false);
+        let conflicted = ("unsaved edited\n".to_string(), true, true);
+
+        // Changes are reported a little after they happen, and not
+        // necessarily all at once.
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        while (state(&context, &clean), state(&context, &edited))
+            != (merged.clone(), conflicted.clone())
+            && Instant::now() < deadline
+        {
+            thread::sleep(Duration::from_millis(50));
+
+            if context.changed() {
+                context.apply(Op::Refresh);
+            }
+        }
+
+        assert_eq!(state(&context, &clean), merged);
+        assert_eq!(state(&context, &edited), conflicted);
+        assert!(context
+            .log()
+            .iter()
+            .any(|note| note.severity == Severity::Warning
+                && note.message == "changed on disk while it has unsaved edits"));
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn notes_are_added_to_the_end_of_the_log_document() {
+        let mut context = Context::new();
+
+        context.apply(Op::Delete(100..101));
+        context.apply(Op::Switch(Name::Log));
+        context.apply(Op::Delete(100..101));
+
+        let lines = context
+            .log()
+            .iter()
+            .map(|note| format!("{}\n", note))
+            .collect::<String>();
+
+        assert_eq!(context.log().len(), 2);
+        assert_eq!(context.buffer().to_string(), lines);
+
+        // Notes from another replica that sort among these are placed by
+        // writing the log out again.
+        let mut other = Log::default();
+        let op = other.record(Severity::Info, Source::Session, "early".into(), 0);
+
+        other.apply(op.clone());
+
+        let mut merged = context.log().clone();
+
+        merged.apply(op.clone());
+        context.apply(Op::Log(op));
+
+        let lines = merged
+            .iter()
+            .map(|note| format!("{}\n", note))
+            .collect::<String>();
+
+        assert_eq!(context.buffer().to_string(), lines);
+    }
+
+    #[test]
+    fn the_log_keeps_its_path_once_saved() {
+        let mut context = Context::new();
+        let path = std::env::temp_dir().join(format!("jago-log-{}", std::process::id()));
+
+        context.apply(Op::Switch(Name::Log));
+        context.apply(Op::SaveAs(path.clone()));
+        context.apply(Op::Delete(100..101));
+
+        assert_eq!(context.focused().path(), Some(path.as_path()));
+
+        context.apply(Op::Save);
+
+        // Everything but the note that it was saved, which comes after.
+        let saved = fs::read_to_string(&path).unwrap();
+
+        assert_eq!(saved.lines().count(), context.log().len() - 1);
+        assert!(context.buffer().to_string().starts_with(&saved));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rendering_into_a_file_is_an_edit_to_it() {
+        let directory = std::env::temp_dir().join(format!("jago-render-{}", std::process::id()));
+        let path = directory.join("values.json");
+
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(&path, "[]").unwrap();
+
+        let mut context = Context::new();
+
+        context.apply(Op::Open(path.clone()));
+        context.render(Format::Json, &vec![1, 2]).unwrap();
+
+        assert_eq!(context.parse::<Vec<u32>>().unwrap(), vec![1, 2]);
+        assert_eq!(context.focused().path(), Some(path.as_path()));
+        assert!(context.focused().dirty());
+
+        context.apply(Op::Save);
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            context.buffer().to_string()
+        );
+
+        context.apply(Op::Undo);
+
+        assert_eq!(context.buffer().to_string(), "[]");
+
+        let _ = fs::remove_dir_all(&directory);
     }
 }
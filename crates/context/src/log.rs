@@ -0,0 +1,313 @@
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Missing {0}")]
+    Missing(#[from] DotRange<Actor>),
+    #[error("AppendOnly")]
+    AppendOnly,
+}
+
+use crate::{Actor, Name};
+
+use ::{
+    chrono::{DateTime, Utc},
+    crdts::{list, CmRDT, DotRange, List},
+    serde::{Deserialize, Serialize},
+    std::fmt::{self, Display},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// What a note is about.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Source {
+    /// The session as a whole, rather than any one document in it.
+    Session,
+    /// Reading the copy directory.
+    Listing,
+    Document(Name),
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Session => write!(f, "session"),
+            Source::Listing => write!(f, "listing"),
+            Source::Document(name) => name.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Note {
+    pub severity: Severity,
+    pub source: Source,
+    pub message: String,
+    pub time: DateTime<Utc>,
+}
+
+impl Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}: {}",
+            self.time.format("%H:%M:%S"),
+            self.severity,
+            self.source,
+            self.message
+        )
+    }
+}
+
+/// Which notes to read: those at least as severe as `severity`, about
+/// `source` and recorded after `since`, for each that is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Filter {
+    pub severity: Option<Severity>,
+    pub source: Option<Source>,
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl Filter {
+    pub fn matches(&self, note: &Note) -> bool {
+        self.severity
+            .is_none_or(|severity| note.severity >= severity)
+            && self
+                .source
+                .as_ref()
+                .is_none_or(|source| note.source == *source)
+            && self.since.is_none_or(|since| note.time > since)
+    }
+}
+
+pub type Op = list::Op<Note, Actor>;
+
+/// What went wrong, or is worth knowing, in every replica of a session.
+/// Notes are only ever appended, each after every one its replica had
+/// seen, so replicas agree on the order once they have the same notes.
+#[derive(Debug, Clone, Default)]
+pub struct Log {
+    notes: List<Note, Actor>,
+}
+
+impl Log {
+    pub fn record(&self, severity: Severity, source: Source, message: String, actor: Actor) -> Op {
+        let note = Note {
+            severity,
+            source,
+            message,
+            time: Utc::now(),
+        };
+
+        self.notes.append(note, actor)
+    }
+
+    pub fn len(&self) -> usize {
+        self.notes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    /// Every note, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &Note> {
+        self.notes.iter()
+    }
+
+    /// Whether `op` adds a note after every one there is, as those recorded
+    /// here always do, rather than among them or again.
+    pub fn appends(&self, op: &Op) -> bool {
+        match op {
+            list::Op::Insert { id, .. } => {
+                self.notes.last_entry().is_none_or(|(last, _)| last < id)
+            }
+            list::Op::Delete { .. } => false,
+        }
+    }
+
+    pub fn query<'a>(&'a self, filter: &'a Filter) -> impl Iterator<Item = &'a Note> {
+        self.iter().filter(|note| filter.matches(note))
+    }
+}
+
+impl CmRDT for Log {
+    type Op = Op;
+    type Validation = Error;
+
+    fn validate_op(&self, op: &Self::Op) -> Result<(), Error> {
+        match op {
+            list::Op::Insert { .. } => Ok(self.notes.validate_op(op)?),
+            list::Op::Delete { .. } => Err(Error::AppendOnly),
+        }
+    }
+
+    fn apply(&mut self, op: Self::Op) {
+        if let list::Op::Insert { .. } = op {
+            self.notes.apply(op);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::Duration};
+
+    fn record(log: &mut Log, severity: Severity, message: &str, actor: Actor) -> Op {
+        let op = log.record(severity, Source::Session, message.into(), actor);
+
+        log.validate_op(&op).unwrap();
+        log.apply(op.clone());
+        op
+    }
+
+    fn messages(log: &Log) -> Vec<&str> {
+        log.iter().map(|note| note.message.as_str()).collect()
+    }
+
+    #[test]
+    fn replicas_agree_on_the_order_whatever_order_they_merge_in() {
+        let (mut first, mut second) = (Log::default(), Log::default());
+
+        let ops = [
+            record(&mut first, Severity::Info, "one", 0),
+            record(&mut first, Severity::Info, "two", 0),
+        ];
+        let theirs = record(&mut second, Severity::Info, "three", 1);
+
+        for op in ops {
+            second.validate_op(&op).unwrap();
+            second.apply(op);
+        }
+
+        first.validate_op(&theirs).unwrap();
+        first.apply(theirs.clone());
+
+        assert_eq!(messages(&first), messages(&second));
+        assert_eq!(first.len(), 3);
+
+        // Seeing a note again changes nothing.
+        first.apply(theirs);
+
+        assert_eq!(messages(&first), messages(&second));
+    }
+
+    #[test]
+    fn notes_recorded_after_merging_come_after_everything_seen() {
+        let (mut first, mut second) = (Log::default(), Log::default());
+
+        let op = record(&mut first, Severity::Info, "one", 0);
+
+        second.apply(op);
+        record(&mut second, Severity::Info, "two", 1);
+
+        assert_eq!(messages(&second), ["one", "two"]);
+    }
+
+    #[test]
+    fn notes_cannot_be_removed_or_skipped() {
+        let (mut first, mut second) = (Log::default(), Log::default());
+
+        let one = record(&mut first, Severity::Info, "one", 0);
+        let two = record(&mut first, Severity::Info, "two", 0);
+
+        assert!(matches!(second.validate_op(&two), Err(Error::Missing(_))));
+
+        second.apply(one);
+
+        let delete = second.notes.delete_index(0, 1).unwrap();
+
+        assert!(matches!(
+            second.validate_op(&delete),
+            Err(Error::AppendOnly)
+        ));
+
+        second.apply(delete);
+
+        assert_eq!(messages(&second), ["one"]);
+    }
+
+    #[test]
+    fn only_notes_after_every_other_are_appends() {
+        let (mut first, mut second) = (Log::default(), Log::default());
+
+        let early = record(&mut first, Severity::Info, "early", 0);
+        record(&mut second, Severity::Info, "one", 1);
+
+        let late = second.record(Severity::Info, Source::Session, "two".into(), 1);
+
+        assert!(second.appends(&late));
+        assert!(!second.appends(&early));
+
+        second.apply(late.clone());
+
+        assert!(!second.appends(&late));
+        assert!(!second.appends(&second.notes.delete_index(0, 1).unwrap()));
+    }
+
+    #[test]
+    fn queries_match_every_part_of_the_filter_that_is_set() {
+        let mut log = Log::default();
+        let start = Utc::now() - Duration::seconds(1);
+
+        record(&mut log, Severity::Info, "info", 0);
+        record(&mut log, Severity::Error, "error", 0);
+
+        let op = log.record(
+            Severity::Warning,
+            Source::Document(Name::Log),
+            "warning".into(),
+            0,
+        );
+
+        log.apply(op);
+
+        let query = |filter: Filter| {
+            log.query(&filter)
+                .map(|note| note.message.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(query(Filter::default()), ["info", "error", "warning"]);
+        assert_eq!(
+            query(Filter {
+                severity: Some(Severity::Warning),
+                ..Filter::default()
+            }),
+            ["error", "warning"]
+        );
+        assert_eq!(
+            query(Filter {
+                source: Some(Source::Session),
+                ..Filter::default()
+            }),
+            ["info", "error"]
+        );
+        assert_eq!(
+            query(Filter {
+                since: Some(start),
+                ..Filter::default()
+            }),
+            ["info", "error", "warning"]
+        );
+        assert!(query(Filter {
+            since: Some(Utc::now()),
+            ..Filter::default()
+        })
+        .is_empty());
+    }
+}
@@ -0,0 +1,500 @@
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Environment {0}")]
+    Environment(#[from] environment::Error),
+    #[error("Io {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Encoding {0}")]
+    Encoding(#[from] encoding::Error),
+    #[error("Buffer {0}")]
+    Buffer(#[from] crate::buffer::Error),
+}
+
+use crate::{
+    buffer::{Op, Snapshot},
+    encoding, write_atomic, Buffer, CmRDT,
+};
+
+use ::{
+    chrono::{DateTime, Utc},
+    instrument::prelude::*,
+    serde::{Deserialize, Serialize},
+    std::{
+        fs::{self, File, OpenOptions},
+        io::{ErrorKind, Write},
+        path::{Path, PathBuf},
+        time::Duration,
+    },
+};
+
+/// How many ops may pile up in the log past the last snapshot.
+static SNAPSHOT_EVERY: usize = 1024;
+
+/// How long appended ops may go without being synced to disk, so typing
+/// doesn't cost a sync per keystroke. Saving and closing sync right away.
+static SYNC_AFTER: Duration = Duration::from_millis(1000);
+
+#[derive(Serialize, Deserialize)]
+struct Stored {
+    ops: usize,
+    state: Snapshot,
+    /// Where in the log the ops after the snapshot start, so loading can
+    /// skip the ones before without decoding them. Older snapshots lack it.
+    #[serde(default)]
+    offset: Option<u64>,
+}
+
+/// A document's history on disk: every op ever applied, framed one after
+/// another behind a single header, and a snapshot of the state after the
+/// first `ops` of them so loading doesn't have to replay the whole log.
+pub struct Store {
+    directory: PathBuf,
+    log: File,
+    length: u64,
+    logged: usize,
+    snapshotted: usize,
+    synced: Option<DateTime<Utc>>,
+}
+
+impl Store {
+    pub fn open(name: &str) -> Result<Self, Error> {
+        Self::at(environment::target("context", true)?.join(name))
+    }
+
+    fn at(directory: PathBuf) -> Result<Self, Error> {
+        fs::create_dir_all(&directory)?;
+
+        Ok(Self {
+            log: append(&directory)?,
+            directory,
+            length: 0,
+            logged: 0,
+            snapshotted: 0,
+            synced: None,
+        })
+    }
+
+    /// Names the store for a file after it, plus a fingerprint of its full
+    /// path so files sharing a name don't share a history.
+    pub fn name(path: &Path) -> String {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        format!("{}-{:016x}", name, fingerprint(&path.to_string_lossy()))
+    }
+
+    /// Rebuilds the buffer from the snapshot and the ops logged after it,
+    /// starting from where the snapshot says they are in the log. A frame
+    /// left half written by a crash is cut off so appends carry on, while a
+    /// whole one that fails to decode is skipped and kept. A history written
+    /// under another major version is set aside and a new one started.
+    pub fn load(&mut self) -> Result<Buffer, Error> {
+        match self.replay() {
+            Err(Error::Encoding(encoding::Error::Version(version))) => {
+                warn!(
+                    "setting aside history {} written as {}",
+                    self.directory.display(),
+                    version
+                );
+
+                self.retire(version)?;
+                self.replay()
+            }
+            result => result,
+        }
+    }
+
+    /// Renames the log and the snapshot after `version`, out of the way of
+    /// the ones that take their place.
+    fn retire(&mut self, version: encoding::Version) -> Result<(), Error> {
+        for name in ["log", "snapshot"] {
+            let path = self.directory.join(name);
+
+            if path.exists() {
+                fs::rename(
+                    &path,
+                    self.directory.join(format!("{}.{}", name, version.major)),
+                )?;
+            }
+        }
+
+        self.log = append(&self.directory)?;
+
+        Ok(())
+    }
+
+    fn replay(&mut self) -> Result<Buffer, Error> {
+        let (mut buffer, snapshotted, offset) = match fs::read(self.directory.join("snapshot")) {
+            Ok(bytes) => {
+                let stored: Stored = encoding::decode(&bytes)?;
+
+                (Buffer::from(stored.state), stored.ops, stored.offset)
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => (Buffer::new(), 0, None),
+            Err(error) => return Err(error.into()),
+        };
+
+        let log = fs::read(self.directory.join("log"))?;
+
+        let mut end = log.len();
+
+        if log.is_empty() {
+            let mut header = vec![];
+
+            encoding::header(&mut header);
+            self.log.write_all(&header)?;
+            self.log.sync_data()?;
+
+            end = header.len();
+        }
+
+        let (version, body) = match log.is_empty() {
+            true => (encoding::Version::current(), &log[..]),
+            false => encoding::version(&log)?,
+        };
+
+        let start = log.len() - body.len();
+
+        // A log cut short by a crash may end before the snapshot's offset,
+        // in which case it is read from the start instead.
+        let (mut rest, mut logged) = match offset.map(|offset| offset as usize) {
+            Some(offset) if (start..=log.len()).contains(&offset) => (&log[offset..], snapshotted),
+            _ => (body, 0),
+        };
+
+        while !rest.is_empty() {
+            let length = log.len() - rest.len();
+
+            let body = match encoding::split_frame(&mut rest) {
+                Ok(body) => body,
+                Err(encoding::Error::Truncated) => {
+                    warn!(
+                        "truncating log {} after op {}",
+                        self.directory.display(),
+                        logged
+                    );
+
+                    self.log.set_len(length as u64)?;
+
+                    end = length;
+
+                    break;
+                }
+                Err(error) => return Err(error.into()),
+            };
+
+            if logged >= snapshotted {
+                match encoding::read::<Op>(body, version) {
+                    Ok(op) => buffer.apply(op),
+                    Err(error) => warn!(
+                        "skipping op {} in log {}: {}",
+                        logged,
+                        self.directory.display(),
+                        error
+                    ),
+                }
+            }
+
+            logged += 1;
+        }
+
+        buffer.drain()?;
+
+        self.length = end as u64;
+        self.logged = logged;
+        self.snapshotted = snapshotted.min(logged);
+
+        Ok(buffer)
+    }
+
+    /// Writes `ops` to the log, syncing it once the last sync is a while
+    /// ago rather than every time.
+    pub fn append(&mut self, ops: &[Op], buffer: &Buffer) -> Result<(), Error> {
+        if ops.is_empty() {
+            return Ok(());
+        }
+
+        let mut bytes = vec![];
+
+        for op in ops {
+            encoding::frame(op, &mut bytes)?;
+        }
+
+        self.log.write_all(&bytes)?;
+
+        self.length += bytes.len() as u64;
+        self.logged += ops.len();
+
+        if self.logged - self.snapshotted >= SNAPSHOT_EVERY {
+            self.snapshot(buffer)?;
+        }
+
+        let due = self.synced.is_none_or(|synced| {
+            (Utc::now() - synced)
+                .to_std()
+                .is_ok_and(|elapsed| elapsed >= SYNC_AFTER)
+        });
+
+        match due {
+            true => self.sync(),
+            false => Ok(()),
+        }
+    }
+
+    /// Makes sure everything appended so far is on disk.
+    pub fn sync(&mut self) -> Result<(), Error> {
+        self.log.sync_data()?;
+
+        self.synced = Some(Utc::now());
+
+        Ok(())
+    }
+
+    /// Saves the state as it is after every op logged so far. The log is
+    /// synced first, so the snapshot never gets ahead of it.
+    pub fn snapshot(&mut self, buffer: &Buffer) -> Result<(), Error> {
+        self.sync()?;
+
+        let stored = Stored {
+            ops: self.logged,
+            state: buffer.snapshot()?,
+            offset: Some(self.length),
+        };
+
+        write_atomic(&self.directory.join("snapshot"), encoding::encode(&stored)?)?;
+
+        self.snapshotted = self.logged;
+
+        Ok(())
+    }
+}
+
+impl Drop for Store {
+    fn drop(&mut self) {
+        if let Err(error) = self.sync() {
+            error!("syncing {}: {}", self.directory.display(), error);
+        }
+    }
+}
+
+fn append(directory: &Path) -> Result<File, Error> {
+    Ok(OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(directory.join("log"))?)
+}
+
+/// FNV-1a, which unlike the std hasher is the same from one build to the
+/// next.
+fn fingerprint(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A store in a directory of its own, emptied first.
+    fn store(name: &str) -> Store {
+        let directory =
+            std::env::temp_dir().join(format!("jago-store-{}-{}", name, std::process::id()));
+
+        let _ = fs::remove_dir_all(&directory);
+
+        Store::at(directory).unwrap()
+    }
+
+    fn reopen(store: Store) -> Store {
+        let directory = store.directory.clone();
+
+        drop(store);
+
+        Store::at(directory).unwrap()
+    }
+
+    fn edit(store: &mut Store, buffer: &mut Buffer, text: &str) {
+        buffer.insert(buffer.len().unwrap(), text, 0).unwrap();
+        store.append(&buffer.drain().unwrap(), buffer).unwrap();
+    }
+
+    #[test]
+    fn replays_the_log() {
+        let mut store = store("replay");
+        let mut buffer = store.load().unwrap();
+
+        edit(&mut store, &mut buffer, "hello");
+        buffer.set_mark("end", 5, 0).unwrap();
+        store.append(&buffer.drain().unwrap(), &buffer).unwrap();
+        edit(&mut store, &mut buffer, " world");
+
+        let mut store = reopen(store);
+        let loaded = store.load().unwrap();
+
+        assert_eq!(loaded.to_string(), "hello world");
+        assert_eq!(loaded.mark("end").unwrap(), Some(5));
+        assert_eq!(store.logged, "hello world".len() + 1);
+
+        let _ = fs::remove_dir_all(&store.directory);
+    }
+
+    #[test]
+    fn loading_skips_what_the_snapshot_covers() {
+        let mut store = store("snapshot");
+        let mut buffer = store.load().unwrap();
+
+        edit(&mut store, &mut buffer, "covered");
+        store.snapshot(&buffer).unwrap();
+        edit(&mut store, &mut buffer, " and after");
+
+        let snapshotted = store.snapshotted;
+        let covered = store.directory.join("log");
+
+        // Were the ops the snapshot covers decoded, this would fail to.
+        let mut log = fs::read(&covered).unwrap();
+        let mut header = vec![];
+
+        encoding::header(&mut header);
+
+        let stored: Stored =
+            encoding::decode(&fs::read(store.directory.join("snapshot")).unwrap()).unwrap();
+
+        log[header.len()..stored.offset.unwrap() as usize].fill(0xff);
+        fs::write(&covered, &log).unwrap();
+
+        let mut store = reopen(store);
+        let loaded = store.load().unwrap();
+
+        assert_eq!(loaded.to_string(), "covered and after");
+        assert_eq!(store.snapshotted, snapshotted);
+        assert_eq!(store.logged, "covered and after".len());
+
+        let _ = fs::remove_dir_all(&store.directory);
+    }
+
+    #[test]
+    fn snapshots_without_an_offset_replay_from_the_start() {
+        let mut store = store("offsetless");
+        let mut buffer = store.load().unwrap();
+
+        edit(&mut store, &mut buffer, "abc");
+
+        let stored = Stored {
+            ops: 2,
+            state: {
+                let mut partial = Buffer::new();
+
+                partial.insert(0, "ab", 0).unwrap();
+                partial.snapshot().unwrap()
+            },
+            offset: None,
+        };
+
+        write_atomic(
+            &store.directory.join("snapshot"),
+            encoding::encode(&stored).unwrap(),
+        )
+        .unwrap();
+
+        edit(&mut store, &mut buffer, "d");
+
+        let mut store = reopen(store);
+
+        assert_eq!(store.load().unwrap().to_string(), "abcd");
+        assert_eq!(store.logged, 4);
+
+        let _ = fs::remove_dir_all(&store.directory);
+    }
+
+    #[test]
+    fn a_torn_frame_is_cut_off() {
+        let mut store = store("torn");
+        let mut buffer = store.load().unwrap();
+
+        edit(&mut store, &mut buffer, "kept");
+
+        let length = store.length;
+
+        store.log.write_all(&[0x7f, 0x01]).unwrap();
+
+        let mut store = reopen(store);
+        let mut buffer = store.load().unwrap();
+
+        assert_eq!(buffer.to_string(), "kept");
+        assert_eq!(store.length, length);
+        assert_eq!(
+            fs::metadata(store.directory.join("log")).unwrap().len(),
+            length
+        );
+
+        edit(&mut store, &mut buffer, "!");
+
+        let mut store = reopen(store);
+
+        assert_eq!(store.load().unwrap().to_string(), "kept!");
+
+        let _ = fs::remove_dir_all(&store.directory);
+    }
+
+    #[test]
+    fn a_whole_frame_that_fails_to_decode_is_skipped_and_kept() {
+        let mut store = store("undecodable");
+        let mut buffer = store.load().unwrap();
+
+        edit(&mut store, &mut buffer, "a");
+
+        // A variant no reader knows of, framed as a newer writer would.
+        store.log.write_all(&[0x02, 0x7f, 0x00]).unwrap();
+        store.length += 3;
+        store.logged += 1;
+
+        edit(&mut store, &mut buffer, "b");
+
+        let length = store.length;
+        let mut store = reopen(store);
+
+        assert_eq!(store.load().unwrap().to_string(), "ab");
+        assert_eq!(store.logged, 3);
+        assert_eq!(
+            fs::metadata(store.directory.join("log")).unwrap().len(),
+            length
+        );
+
+        let _ = fs::remove_dir_all(&store.directory);
+    }
+
+    #[test]
+    fn histories_of_another_major_version_are_set_aside() {
+        let store = store("major");
+        let mut old = b"jago".to_vec();
+
+        old.extend([encoding::MAJOR - 1, 0, 0x7f]);
+
+        fs::write(store.directory.join("log"), &old).unwrap();
+        fs::write(store.directory.join("snapshot"), &old).unwrap();
+
+        let mut store = reopen(store);
+        let mut buffer = store.load().unwrap();
+
+        assert_eq!(buffer.to_string(), "");
+
+        edit(&mut store, &mut buffer, "new");
+
+        let mut store = reopen(store);
+
+        assert_eq!(store.load().unwrap().to_string(), "new");
+
+        for name in ["log", "snapshot"] {
+            let retired = store
+                .directory
+                .join(format!("{}.{}", name, encoding::MAJOR - 1));
+
+            assert_eq!(fs::read(retired).unwrap(), old);
+        }
+
+        let _ = fs::remove_dir_all(&store.directory);
+    }
+}
@@ -0,0 +1,498 @@
+use crate::{Annotation, Binding, Context, Error, Name, Note, Position, Query, Severity, Style};
+
+use ::{
+    serde::{Deserialize, Serialize},
+    std::ops::Range,
+};
+
+/// What a frontend keeps between draws and brings to the next one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Frame {
+    /// The first line shown last time, kept unless the cursor has left it
+    /// behind.
+    pub top: usize,
+    /// How many rows there is room for, text and panels together, or `None`
+    /// to lay out all of the text.
+    pub rows: Option<usize>,
+    /// The query being typed, shown instead of the search in effect.
+    pub prompt: Option<Query>,
+    /// Whether to show the log.
+    pub log: bool,
+    /// How many notes the log had when it was last shown. Warnings and
+    /// errors since are shown while it is hidden.
+    pub seen: usize,
+    /// The key bindings to list, if any.
+    pub bindings: Vec<Binding>,
+}
+
+/// A context laid out for drawing, the same for every frontend.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct View {
+    /// The first line shown, scrolled as little as needed to show the cursor.
+    pub top: usize,
+    pub lines: Vec<Line>,
+    /// The cursor, as a line of `lines` and a char in it.
+    pub cursor: Position,
+    pub status: Status,
+    /// What is shown below the text, in order.
+    pub panels: Vec<Panel>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Line {
+    pub spans: Vec<Span>,
+}
+
+impl Line {
+    pub fn text(&self) -> String {
+        self.spans.iter().map(|span| span.text.as_str()).collect()
+    }
+}
+
+/// A run of chars that look the same.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub text: String,
+    pub look: Look,
+}
+
+/// How text is drawn: whether it is selected or under another cursor,
+/// whether it matches the search, and the styles of the spans over it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Look {
+    pub selected: bool,
+    pub matched: bool,
+    pub styles: Vec<Style>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Status {
+    pub name: Name,
+    pub dirty: bool,
+    pub conflicted: bool,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tab {
+    pub name: Name,
+    pub dirty: bool,
+    pub current: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Panel {
+    Bindings(Vec<Binding>),
+    Log(Vec<Note>),
+    /// The warnings and errors logged since the log was last shown.
+    Alerts(Vec<Note>),
+    /// The messages of the diagnostics the cursor is in.
+    Diagnostics(Vec<String>),
+    /// The document changed on disk while it had unsaved edits.
+    Conflict,
+    Search(Query),
+    /// The open documents, when there is more than one.
+    Documents(Vec<Tab>),
+}
+
+impl Panel {
+    /// How many rows the panel takes on a screen.
+    pub fn height(&self) -> usize {
+        match self {
+            Panel::Bindings(bindings) => bindings.len(),
+            Panel::Log(notes) | Panel::Alerts(notes) => notes.len(),
+            Panel::Diagnostics(messages) => messages.len(),
+            Panel::Conflict | Panel::Search(_) | Panel::Documents(_) => 1,
+        }
+    }
+}
+
+pub(crate) fn view(context: &Context, frame: &Frame) -> Result<View, Error> {
+    let buffer = context.buffer();
+
+    let selections = buffer.selections(context.actor())?;
+    let matches = context.matches();
+    let annotations = buffer.annotations()?;
+
+    let cursor = selections.last().map_or(0, |selection| selection.head);
+    let position = buffer.offset_to_position(cursor)?;
+
+    let panels = panels(context, frame, &annotations, cursor);
+
+    let count = buffer.line_count()?;
+
+    let (top, rows) = match frame.rows {
+        Some(rows) => {
+            let height = panels.iter().map(Panel::height).sum::<usize>();
+            let rows = rows.saturating_sub(height).max(1);
+
+            (follow(frame.top, rows, position.line), rows)
+        }
+        None => (0, count),
+    };
+
+    // Other cursors show as the char they are at.
+    let mut selected = selections
+        .iter()
+        .filter_map(|selection| match selection.is_empty() {
+            false => Some(selection.range()),
+            true if selection.head != cursor => Some(selection.head..selection.head + 1),
+            true => None,
+        })
+        .collect::<Vec<_>>();
+
+    selected.sort_by_key(|range| range.start);
+
+    // Lines are laid out in order, so each of these only moves forward.
+    let mut selected = Sweep::new(&selected, Range::clone);
+    let mut matched = Sweep::new(&matches, Range::clone);
+    let mut styled = Sweep::new(&annotations, |annotation| annotation.range.clone());
+
+    let lines = (top..(top + rows).min(count))
+        .map(|line| {
+            let Range { start, .. } = buffer.line_span(line)?;
+            let text = buffer.line(line)?;
+
+            let looks = text.chars().enumerate().map(|(column, c)| {
+                let offset = start + column;
+
+                let look = Look {
+                    selected: !selected.at(offset).is_empty(),
+                    matched: !matched.at(offset).is_empty(),
+                    styles: styled
+                        .at(offset)
+                        .iter()
+                        .map(|annotation| annotation.style.clone())
+                        .collect(),
+                };
+
+                (c, look)
+            });
+
+            let mut spans: Vec<Span> = vec![];
+
+            for (c, look) in looks {
+                match spans.last_mut() {
+                    Some(span) if span.look == look => span.text.push(c),
+                    _ => spans.push(Span {
+                        text: c.to_string(),
+                        look,
+                    }),
+                }
+            }
+
+            Ok(Line { spans })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(View {
+        top,
+        lines,
+        cursor: Position::new(position.line - top, position.column),
+        status: Status {
+            name: context.current().clone(),
+            dirty: context.dirty(),
+            conflicted: context.conflicted(),
+            position,
+        },
+        panels,
+    })
+}
+
+/// The panels to show, cut down to fit when there is only so much room: the
+/// bindings and the log to a third of it each, and the rest to half,
+/// dropping the oldest alerts.
+fn panels(
+    context: &Context,
+    frame: &Frame,
+    annotations: &[Annotation],
+    cursor: usize,
+) -> Vec<Panel> {
+    let log = context.log();
+
+    let mut panels = vec![];
+
+    if !frame.bindings.is_empty() {
+        let shown = frame.rows.map_or(frame.bindings.len(), |rows| rows / 3);
+
+        panels.push(Panel::Bindings(
+            frame.bindings.iter().take(shown).cloned().collect(),
+        ));
+    }
+
+    if frame.log {
+        let shown = frame.rows.map_or(log.len(), |rows| rows / 3);
+
+        panels.push(Panel::Log(
+            log.iter()
+                .skip(log.len().saturating_sub(shown))
+                .cloned()
+                .collect(),
+        ));
+    }
+
+    let mut alerts = match frame.log {
+        true => vec![],
+        false => log
+            .iter()
+            .skip(frame.seen)
+            .filter(|note| note.severity >= Severity::Warning)
+            .cloned()
+            .collect::<Vec<_>>(),
+    };
+
+    let diagnostics = annotations
+        .iter()
+        .filter(|annotation| (annotation.range.start..=annotation.range.end).contains(&cursor))
+        .filter_map(|annotation| match &annotation.style {
+            Style::Diagnostic(message) => Some(message.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut rest = vec![];
+
+    if !diagnostics.is_empty() {
+        rest.push(Panel::Diagnostics(diagnostics));
+    }
+
+    if context.conflicted() {
+        rest.push(Panel::Conflict);
+    }
+
+    if let Some(query) = frame.prompt.as_ref().or_else(|| context.search()) {
+        rest.push(Panel::Search(query.clone()));
+    }
+
+    if context.names().count() > 1 {
+        let tabs = context
+            .names()
+            .map(|name| Tab {
+                name: name.clone(),
+                dirty: context
+                    .document(name)
+                    .is_some_and(|document| document.dirty()),
+                current: name == context.current(),
+            })
+            .collect();
+
+        rest.push(Panel::Documents(tabs));
+    }
+
+    if let Some(rows) = frame.rows {
+        let room = (rows / 2).saturating_sub(rest.iter().map(Panel::height).sum());
+
+        alerts.drain(..alerts.len().saturating_sub(room));
+    }
+
+    if !alerts.is_empty() {
+        panels.push(Panel::Alerts(alerts));
+    }
+
+    panels.extend(rest);
+
+    panels
+}
+
+/// Which of some ranges, ordered by where they start, cover each offset
+/// asked about, for offsets asked about in order. Those covering one stay
+/// in the order they came in.
+struct Sweep<'a, T> {
+    items: &'a [T],
+    range: fn(&T) -> Range<usize>,
+    next: usize,
+    covering: Vec<&'a T>,
+}
+
+impl<'a, T> Sweep<'a, T> {
+    fn new(items: &'a [T], range: fn(&T) -> Range<usize>) -> Self {
+        Self {
+            items,
+            range,
+            next: 0,
+            covering: vec![],
+        }
+    }
+
+    fn at(&mut self, offset: usize) -> &[&'a T] {
+        while let Some(item) = self.items.get(self.next) {
+            if (self.range)(item).start > offset {
+                break;
+            }
+
+            self.covering.push(item);
+            self.next += 1;
+        }
+
+        let range = self.range;
+
+        self.covering.retain(|item| range(item).end > offset);
+
+        &self.covering
+    }
+}
+
+/// Scrolls from `top` as little as needed for `line` to be among `rows`.
+fn follow(top: usize, rows: usize, line: usize) -> usize {
+    if line < top {
+        line
+    } else if line >= top + rows {
+        line + 1 - rows
+    } else {
+        top
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CmRDT, Op};
+
+    fn context(text: &str) -> Context {
+        let mut context = Context::new();
+
+        context.apply(Op::Insert(0, text.into()));
+        context
+    }
+
+    /// Each span of a line, with whether it is selected and matched and how
+    /// many styles it has.
+    fn looks(view: &View, line: usize) -> Vec<(&str, bool, bool, usize)> {
+        view.lines[line]
+            .spans
+            .iter()
+            .map(|span| {
+                (
+                    span.text.as_str(),
+                    span.look.selected,
+                    span.look.matched,
+                    span.look.styles.len(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spans_split_where_the_look_changes() {
+        let mut context = context("one two three\nfour");
+
+        context.apply(Op::Annotate(4..7, Style::Bold));
+        context.apply(Op::Annotate(6..11, Style::Italic));
+        context.apply(Op::Search(Query::literal("three")));
+        context.apply(Op::Select(0..3));
+
+        let view = view(&context, &Frame::default()).unwrap();
+
+        assert_eq!(
+            looks(&view, 0),
+            vec![
+                ("one", true, false, 0),
+                (" ", false, false, 0),
+                ("tw", false, false, 1),
+                ("o", false, false, 2),
+                (" ", false, false, 1),
+                ("thr", false, true, 1),
+                ("ee", false, true, 0),
+            ]
+        );
+        assert_eq!(
+            view.lines[0].spans[3].look.styles,
+            vec![Style::Bold, Style::Italic]
+        );
+        assert_eq!(looks(&view, 1), vec![("four", false, false, 0)]);
+        assert_eq!(view.cursor, Position::new(0, 3));
+    }
+
+    #[test]
+    fn other_cursors_show_as_the_char_they_are_at() {
+        let mut context = context("ab ab ab");
+
+        context.apply(Op::Select(0..2));
+        context.apply(Op::SelectNext);
+        context.apply(Op::Deselect);
+
+        let view = view(&context, &Frame::default()).unwrap();
+
+        // The primary cursor, after the second "ab", is drawn by the
+        // frontend rather than as a selected char.
+        assert_eq!(
+            looks(&view, 0),
+            vec![
+                ("ab", false, false, 0),
+                (" ", true, false, 0),
+                ("ab ab", false, false, 0),
+            ]
+        );
+        assert_eq!(view.cursor, Position::new(0, 5));
+    }
+
+    #[test]
+    fn the_view_follows_the_cursor() {
+        let text = (0..10)
+            .map(|line| format!("line {}\n", line))
+            .collect::<String>();
+        let mut context = context(&text);
+
+        let line = |line: usize| line * "line 0\n".len();
+        let frame = |top| Frame {
+            top,
+            rows: Some(3),
+            ..Frame::default()
+        };
+
+        context.apply(Op::Select(line(5)..line(5)));
+
+        let view = view(&context, &frame(0)).unwrap();
+
+        assert_eq!(view.top, 3);
+        assert_eq!(view.lines.len(), 3);
+        assert_eq!(view.lines[2].text(), "line 5");
+        assert_eq!(view.cursor, Position::new(2, 0));
+
+        // Kept while the cursor is still on screen.
+        assert_eq!(super::view(&context, &frame(4)).unwrap().top, 4);
+
+        context.apply(Op::Select(line(1)..line(1)));
+
+        assert_eq!(super::view(&context, &frame(4)).unwrap().top, 1);
+
+        // Everything without a number of rows.
+        let view = super::view(&context, &Frame::default()).unwrap();
+
+        assert_eq!(view.top, 0);
+        assert_eq!(view.lines.len(), 11);
+    }
+
+    #[test]
+    fn panels_take_their_rows_from_the_text() {
+        let context = context("one\ntwo\nthree\n");
+
+        let frame = Frame {
+            rows: Some(6),
+            bindings: vec![
+                Binding {
+                    keys: "ctrl-s".into(),
+                    command: crate::Command::Save,
+                };
+                5
+            ],
+            ..Frame::default()
+        };
+
+        let view = view(&context, &frame).unwrap();
+
+        assert_eq!(
+            view.panels,
+            vec![Panel::Bindings(frame.bindings[..2].to_vec())]
+        );
+        assert_eq!(view.lines.len(), 4);
+    }
+
+    #[test]
+    fn following_scrolls_as_little_as_it_can() {
+        assert_eq!(follow(0, 3, 2), 0);
+        assert_eq!(follow(0, 3, 3), 1);
+        assert_eq!(follow(5, 3, 2), 2);
+        assert_eq!(follow(5, 3, 7), 5);
+    }
+}
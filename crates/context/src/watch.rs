@@ -0,0 +1,196 @@
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Notify {0}")]
+    Notify(#[from] notify::Error),
+}
+
+use ::{
+    instrument::prelude::*,
+    notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher as _},
+    std::{
+        collections::BTreeSet,
+        path::{Path, PathBuf},
+        sync::mpsc::{channel, Receiver},
+        time::Duration,
+    },
+};
+
+/// How long a path has to stay quiet before its changes are reported, so a
+/// save that writes, renames and touches a file shows up once.
+static DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watches the copy directory and the documents that are open, collecting
+/// the paths that changed until they are taken.
+pub struct Watcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+    directory: Option<PathBuf>,
+    documents: BTreeSet<PathBuf>,
+    parents: BTreeSet<PathBuf>,
+    changed: BTreeSet<PathBuf>,
+}
+
+impl Watcher {
+    pub fn new() -> Result<Self, Error> {
+        let (sender, events) = channel();
+
+        Ok(Self {
+            watcher: notify::watcher(sender, DEBOUNCE)?,
+            events,
+            directory: None,
+            documents: BTreeSet::new(),
+            parents: BTreeSet::new(),
+            changed: BTreeSet::new(),
+        })
+    }
+
+    pub fn directory(&mut self, path: &Path) -> Result<(), Error> {
+        if let Some(previous) = self.directory.take() {
+            self.watcher.unwatch(previous)?;
+        }
+
+        self.watcher.watch(path, RecursiveMode::Recursive)?;
+        self.directory = Some(path.to_path_buf());
+
+        Ok(())
+    }
+
+    /// Watches the folders holding `paths` rather than the files themselves,
+    /// since saving by rename, as most tools do, swaps out the file being
+    /// watched. Folders no longer holding any of them are let go.
+    pub fn documents(&mut self, paths: BTreeSet<PathBuf>) -> Result<(), Error> {
+        let parents = paths
+            .iter()
+            .filter_map(|path| path.parent())
+            .filter(|parent| {
+                !self
+                    .directory
+                    .as_ref()
+                    .is_some_and(|directory| parent.starts_with(directory))
+            })
+            .map(Path::to_path_buf)
+            .collect::<BTreeSet<_>>();
+
+        for parent in self.parents.difference(&parents) {
+            self.watcher.unwatch(parent)?;
+        }
+
+        for parent in parents.difference(&self.parents) {
+            self.watcher.watch(parent, RecursiveMode::NonRecursive)?;
+        }
+
+        self.documents = paths;
+        self.parents = parents;
+
+        Ok(())
+    }
+
+    /// Collects whatever was reported since the last call, returning whether
+    /// anything is waiting to be taken.
+    pub fn poll(&mut self) -> bool {
+        for event in self.events.try_iter() {
+            match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Remove(path) => {
+                    self.changed.insert(path);
+                }
+                DebouncedEvent::Rename(from, to) => {
+                    self.changed.insert(from);
+                    self.changed.insert(to);
+                }
+                DebouncedEvent::Rescan => {
+                    self.changed.extend(self.directory.clone());
+                    self.changed.extend(self.documents.iter().cloned());
+                }
+                DebouncedEvent::Error(error, path) => {
+                    warn!("watching {:?}: {}", path, error);
+                }
+                DebouncedEvent::NoticeWrite(_)
+                | DebouncedEvent::NoticeRemove(_)
+                | DebouncedEvent::Chmod(_) => {}
+            }
+        }
+
+        !self.changed.is_empty()
+    }
+
+    pub fn take(&mut self) -> BTreeSet<PathBuf> {
+        self.poll();
+
+        std::mem::take(&mut self.changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{fs, thread, time::Instant},
+    };
+
+    /// Waits for the watcher to report `expected`, or gives up after a while.
+    fn changes(watcher: &mut Watcher, expected: &BTreeSet<PathBuf>) -> BTreeSet<PathBuf> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut changed = BTreeSet::new();
+
+        while !changed.is_superset(expected) && Instant::now() < deadline {
+            thread::sleep(DEBOUNCE / 4);
+
+            if watcher.poll() {
+                changed.extend(watcher.take());
+            }
+        }
+
+        changed
+    }
+
+    #[test]
+    fn documents_are_reported_even_when_saved_by_rename() {
+        let directory = std::env::temp_dir().join(format!("jago-watch-{}", std::process::id()));
+        let (watched, other) = (directory.join("watched"), directory.join("other"));
+
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(&watched, "before").unwrap();
+
+        let mut watcher = Watcher::new().unwrap();
+
+        watcher
+            .documents([watched.clone()].into_iter().collect())
+            .unwrap();
+
+        assert!(!watcher.poll());
+
+        fs::write(&other, "after").unwrap();
+        fs::rename(&other, &watched).unwrap();
+
+        let expected = [watched.clone()].into_iter().collect();
+
+        assert!(changes(&mut watcher, &expected).contains(&watched));
+        assert!(watcher.take().is_empty());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn folders_no_longer_holding_documents_are_let_go() {
+        let directory = std::env::temp_dir().join(format!("jago-unwatch-{}", std::process::id()));
+        let document = directory.join("document");
+
+        fs::create_dir_all(&directory).unwrap();
+
+        let mut watcher = Watcher::new().unwrap();
+
+        watcher
+            .documents([document.clone()].into_iter().collect())
+            .unwrap();
+        watcher.documents(BTreeSet::new()).unwrap();
+
+        fs::write(&document, "unwatched").unwrap();
+        thread::sleep(DEBOUNCE * 3);
+
+        assert!(!watcher.poll());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
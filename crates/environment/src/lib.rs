@@ -18,6 +18,12 @@ pub fn copy_directory() -> Result<PathBuf, Error> {
         .map(|home| home.join("copy"))
 }
 
+pub fn config() -> Result<PathBuf, Error> {
+    dirs::config_dir()
+        .ok_or(Error::NoHome)
+        .map(|config| config.join("jago"))
+}
+
 pub fn target(suffix: &str, ensure: bool) -> Result<PathBuf, Error> {
     dirs::home_dir()
         .ok_or(Error::NoHome)
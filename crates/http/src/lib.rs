@@ -15,16 +15,33 @@ pub enum Error {
 }
 
 use ::{
-    axum::{http::StatusCode, response, routing::get, routing::get_service, Router},
+    axum::{
+        extract::Query,
+        http::StatusCode,
+        response::{
+            self,
+            sse::{Event, KeepAlive, Sse},
+        },
+        routing::get,
+        routing::get_service,
+        Json, Router,
+    },
+    context::{CmRDT, Filter, Keymap, Note, Op},
+    futures_util::stream::{self, Stream},
     instrument::prelude::*,
     std::{
         path::Path,
         sync::{mpsc::channel, Arc, Mutex},
+        time::Duration,
     },
-    tokio::runtime::Runtime,
-    tower_http::{services::ServeDir, trace::TraceLayer},
+    tokio::{runtime::Runtime, sync::broadcast, time},
 };
 
+use tower_http::{services::ServeDir, trace::TraceLayer};
+
+/// How often to check for changes on disk.
+static REFRESH: Duration = Duration::from_millis(250);
+
 pub struct Context {
     inner: Arc<Mutex<context::Context>>,
 }
@@ -37,21 +54,94 @@ impl From<context::Context> for Context {
     }
 }
 
-pub fn watch(_: impl Into<Context>) -> Result<(), Error> {
+pub fn watch(context: impl Into<Context>) -> Result<(), Error> {
+    let context = context.into();
+
     let target = pack::browser()?;
 
     let runtime = Runtime::new()?;
 
-    runtime.block_on(async { serve(&target).await })
+    runtime.spawn(refresh(context.inner.clone()));
+
+    runtime.block_on(async { serve(&target, context).await })
 }
 
 static DOCUMENT: &'static str = include_str!("../../browser/browser.html");
 
-async fn serve(target: &Path) -> Result<(), Error> {
+/// Applies whatever changed on disk, so subscribers hear of it.
+async fn refresh(inner: Arc<Mutex<context::Context>>) {
+    let mut interval = time::interval(REFRESH);
+
+    loop {
+        interval.tick().await;
+
+        if let Ok(mut inner) = inner.lock() {
+            if inner.changed() {
+                inner.apply(Op::Refresh);
+            }
+        }
+    }
+}
+
+/// Streams the context's events as JSON. A client that falls behind is sent
+/// a `lagged` event and should fetch everything again.
+fn events(
+    receiver: broadcast::Receiver<context::Event>,
+) -> impl Stream<Item = Result<Event, axum::Error>> {
+    stream::unfold(receiver, |mut receiver| async move {
+        let event = match receiver.recv().await {
+            Ok(event) => Event::default().json_data(event).map_err(axum::Error::new),
+            Err(broadcast::error::RecvError::Lagged(_)) => Ok(Event::default().event("lagged")),
+            Err(broadcast::error::RecvError::Closed) => return None,
+        };
+
+        Some((event, receiver))
+    })
+}
+
+async fn serve(target: &Path, context: Context) -> Result<(), Error> {
     info!("target: {}", target.display());
 
+    let inner = context.inner;
+    let log = inner.clone();
+
     let router = Router::new()
         .route("/", get(|| async { response::Html(DOCUMENT) }))
+        .route(
+            "/events",
+            get(move || {
+                let receiver = inner.lock().ok().map(|inner| inner.subscribe());
+
+                async move {
+                    match receiver {
+                        Some(receiver) => {
+                            Ok(Sse::new(events(receiver)).keep_alive(KeepAlive::new()))
+                        }
+                        None => Err(StatusCode::INTERNAL_SERVER_ERROR),
+                    }
+                }
+            }),
+        )
+        .route(
+            "/log",
+            get(move |Query(filter): Query<Filter>| {
+                let notes = log
+                    .lock()
+                    .ok()
+                    .map(|inner| inner.log().query(&filter).cloned().collect::<Vec<Note>>());
+
+                async move { notes.map(Json).ok_or(StatusCode::INTERNAL_SERVER_ERROR) }
+            }),
+        )
+        .route(
+            "/keymap",
+            get(|| async {
+                Keymap::source().map_err(|error| {
+                    warn!("keymap: {}", error);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })
+            }),
+        )
         .nest(
             "/target",
             get_service(ServeDir::new(target)).handle_error(|error: std::io::Error| async move {
@@ -6,13 +6,10 @@ pub enum Error {
     Environment(#[from] environment::Error),
     #[error("WasmPack {0}")]
     WasmPack(Box<dyn std::error::Error + 'static>),
-    #[error("Notify {0}")]
-    Notify(#[from] notify::Error),
 }
 
 use ::{
     instrument::prelude::*,
-    notify::{watcher, DebouncedEvent, RecursiveMode, Watcher},
     std::{
         path::{Path, PathBuf},
         sync::mpsc::Sender,
@@ -1,4 +1,7 @@
-use ::{context::Context, instrument::prelude::*};
+use ::{
+    context::{CmRDT, Context, Op},
+    instrument::prelude::*,
+};
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
@@ -18,7 +21,21 @@ pub fn main() {
 
     info!("Starting execution 🧨");
 
-    let context = Context::new();
+    #[allow(unused_mut)]
+    let mut context = Context::restore().unwrap_or_else(|error| {
+        warn!("restoring: {}", error);
+        Context::new()
+    });
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if context.buffer().is_empty().unwrap_or(true) {
+        context.apply(Op::Read);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(error) = context.watch() {
+        warn!("watching: {}", error);
+    }
 
     #[cfg(feature = "serve")]
     if let Err(error) = http::watch(context) {